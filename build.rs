@@ -0,0 +1,28 @@
+//
+// Exposes build information to the crate as environment variables.
+//
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+        .unwrap_or("unknown".to_string())
+}
+
+fn main() {
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let rustc = env::var("RUSTC").unwrap_or("rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    let build_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    println!("cargo:rustc-env=ZORCA_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=ZORCA_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=ZORCA_BUILD_TIME={}", build_time);
+}
@@ -0,0 +1,56 @@
+//
+// TLS termination for zorca web server.
+//
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::{Future, Stream};
+
+use tokio_core::reactor::Handle;
+use tokio_signal::unix::{Signal, SIGHUP};
+
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+
+use config::WebTls;
+use errors::CombinedError;
+
+
+// Acceptor could be replaced on certificate reload, connections already
+// accepted keep using the old one.
+pub type SharedAcceptor = Rc<RefCell<SslAcceptor>>;
+
+
+pub fn make_acceptor(config: &WebTls) -> Result<SslAcceptor, CombinedError> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+
+    builder.set_private_key_file(&config.key, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(&config.cert)?;
+    builder.check_private_key()?;
+
+    if let Some(ref ca) = config.client_ca {
+        builder.set_ca_file(ca)?;
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+
+    Ok(builder.build())
+}
+
+/// Rereads certificate and key on SIGHUP, previous acceptor is kept on error.
+pub fn spawn_reload_on_sighup(handle: &Handle, config: WebTls, acceptor: SharedAcceptor) {
+    let reload = Signal::new(SIGHUP, handle)
+        .flatten_stream()
+        .map_err(CombinedError::IOError)
+        .for_each(move |_| {
+            match make_acceptor(&config) {
+                Ok(fresh) => {
+                    println!("web certificate {} has been reloaded", config.cert);
+                    *acceptor.borrow_mut() = fresh;
+                },
+                Err(e) => println!("failed to reload web certificate, keeping previous one: {:?}", e),
+            };
+            Ok(())
+        })
+        .map_err(|e| println!("certificate reload handler stopped with error {:?}", e));
+
+    handle.spawn(reload);
+}
@@ -0,0 +1,235 @@
+//
+// Authentication and authorization of zorca web api clients.
+//
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::mem;
+use std::rc::Rc;
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64;
+use openssl::memcmp;
+use sha1::Sha1;
+use time;
+
+use futures::{Future, Stream};
+use futures::stream;
+
+use tokio_core::reactor::{Handle, Interval};
+
+use hyper::header::Headers;
+
+use config::WebAuth;
+use errors::CombinedError;
+use secure::{SharedProxy, SyncedTicketInfo};
+
+
+const SHA_PREFIX: &str = "{SHA}";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Role {
+    Viewer,
+    Admin,
+}
+
+impl Role {
+    pub fn from_str(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "admin" => Some(Role::Admin),
+            _ => None
+        }
+    }
+}
+
+// Header values which peers sharing zorca's secure credentials would send.
+struct AcceptedTickets {
+    current: Option<String>,
+    // Replaced ticket along with its expiration time, peers could still
+    // hold it till then.
+    previous: Option<(String, i64)>,
+}
+
+pub struct Authenticator {
+    config: WebAuth,
+    // mapping: user -> base64 encoded sha1 of password
+    passwords: HashMap<String, String>,
+    tickets: Rc<RefCell<AcceptedTickets>>,
+}
+
+impl Authenticator {
+    pub fn new(config: &WebAuth) -> Result<Authenticator, CombinedError> {
+        let passwords = match config.htpasswd {
+            Some(ref path) => read_htpasswd(path)?,
+            None => HashMap::new()
+        };
+
+        Ok(Authenticator {
+            config: config.clone(),
+            passwords,
+            tickets: Rc::new(RefCell::new(AcceptedTickets { current: None, previous: None })),
+        })
+    }
+
+    pub fn accepts_ticket(&self) -> bool {
+        self.config.ticket_role.is_some()
+    }
+
+    /// Replaces accepted ticket, the previous one is still accepted till
+    /// `expires_at`.
+    pub fn set_ticket(&self, ticket: Option<String>, expires_at: i64) {
+        let mut tickets = self.tickets.borrow_mut();
+        if tickets.current == ticket {
+            return;
+        }
+
+        let previous = mem::replace(&mut tickets.current, ticket);
+        tickets.previous = previous.map(|previous| (previous, expires_at));
+    }
+
+    /// Resolves role of request issuer, `None` if request is not authenticated.
+    pub fn authenticate(&self, headers: &Headers) -> Option<Role> {
+        let header = headers.get_raw("authorization")
+            .and_then(|raw| raw.one())
+            .and_then(|raw| str::from_utf8(raw).ok());
+
+        let header = match header {
+            Some(header) => header.trim(),
+            None => return self.config.anonymous_role
+        };
+
+        let mut parts = header.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            // Secure backends issue tickets with the same scheme as tokens.
+            (Some("Bearer"), Some(token)) => self.check_token(token.trim()).or_else(|| self.check_ticket(header)),
+            (Some("Basic"), Some(credentials)) => self.check_basic(credentials.trim()),
+            _ => self.check_ticket(header),
+        }
+    }
+
+    fn check_basic(&self, credentials: &str) -> Option<Role> {
+        let decoded = base64::decode(credentials).ok()
+            .and_then(|raw| String::from_utf8(raw).ok());
+
+        let decoded = match decoded {
+            Some(decoded) => decoded,
+            None => return None
+        };
+
+        let mut parts = decoded.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(user), Some(password)) => match self.passwords.get(user) {
+                Some(hash) if secure_eq(hash, &sha1_base64(password)) =>
+                    Some(self.config.users.get(user).cloned().unwrap_or(Role::Viewer)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    // Every token is compared, so response time doesn't tell which one
+    // is close to the given.
+    fn check_token(&self, token: &str) -> Option<Role> {
+        self.config.tokens.iter().fold(None, |found, (known, role)| match secure_eq(known, token) {
+            true => Some(*role),
+            false => found
+        })
+    }
+
+    fn check_ticket(&self, header: &str) -> Option<Role> {
+        let now = time::get_time().sec;
+        let tickets = self.tickets.borrow();
+
+        let current = tickets.current.as_ref()
+            .map_or(false, |ticket| secure_eq(ticket, header));
+        let previous = match tickets.previous {
+            Some((ref ticket, expires_at)) => now < expires_at && secure_eq(ticket, header),
+            None => false
+        };
+
+        match current || previous {
+            true => self.config.ticket_role,
+            false => None
+        }
+    }
+}
+
+// Compares secrets in time independent of where they differ.
+fn secure_eq(known: &str, given: &str) -> bool {
+    known.len() == given.len() && memcmp::eq(known.as_bytes(), given.as_bytes())
+}
+
+/// Periodically updates ticket accepted by authenticator from secure proxy,
+/// replaced ticket is accepted till proxy reports it expired, or for one
+/// more period if expiration is unknown.
+pub fn spawn_ticket_refresh(
+    handle: &Handle,
+    proxy: SharedProxy,
+    stat: Arc<SyncedTicketInfo>,
+    auth: Rc<Authenticator>,
+    period: Duration)
+    -> Result<(), CombinedError>
+{
+    let ticks = stream::once(Ok(()))
+        .chain(Interval::new(period, handle)?)
+        .map_err(CombinedError::IOError);
+
+    let refresh = ticks
+        .for_each(move |_| {
+            let auth = Rc::clone(&auth);
+            let expires_at = match stat.read().unwrap().expires_at {
+                0 => time::get_time().sec + period.as_secs() as i64,
+                expires_at => expires_at
+            };
+
+            proxy.borrow_mut().ticket_as_header().then(move |result| {
+                match result {
+                    Ok(ticket) => auth.set_ticket(ticket, expires_at),
+                    Err(e) => println!("failed to refresh web api ticket {:?}", e),
+                };
+                Ok(())
+            })
+        })
+        .map_err(|e| println!("web api ticket refresh stopped with error {:?}", e));
+
+    handle.spawn(refresh);
+    Ok(())
+}
+
+fn sha1_base64(password: &str) -> String {
+    let mut sha = Sha1::new();
+    sha.update(password.as_bytes());
+    base64::encode(&sha.digest().bytes())
+}
+
+// Only `{SHA}` hashes are supported (htpasswd -s).
+fn read_htpasswd(path: &str) -> Result<HashMap<String, String>, CombinedError> {
+    let mut passwords = HashMap::new();
+
+    for (num, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(user), Some(hash)) if hash.starts_with(SHA_PREFIX) => {
+                passwords.insert(user.to_string(), hash[SHA_PREFIX.len()..].to_string());
+            },
+            _ => {
+                let message = format!("{}:{}: unsupported htpasswd record, only {} hashes are allowed", path, num + 1, SHA_PREFIX);
+                return Err(CombinedError::ConfigError(message));
+            }
+        }
+    }
+
+    Ok(passwords)
+}
@@ -0,0 +1,52 @@
+//
+// Exponential backoff for reconnect attempts, so unicorn isn't hammered by
+// every zorca instance at once while it is down.
+//
+use std::cmp;
+use std::time::Duration;
+
+
+pub const DEFAULT_INITIAL_SEC: u64 = 1;
+pub const DEFAULT_MAX_SEC: u64 = 5 * 60;
+
+
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    pub initial_sec: u64,
+    pub max_sec: u64,
+}
+
+impl BackoffConfig {
+    pub fn new() -> BackoffConfig {
+        BackoffConfig {
+            initial_sec: DEFAULT_INITIAL_SEC,
+            max_sec: DEFAULT_MAX_SEC,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Backoff {
+    config: BackoffConfig,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Backoff {
+        Backoff { config, attempt: 0 }
+    }
+
+    /// Delay before next attempt, doubled on each call up to the maximum.
+    pub fn next(&mut self) -> Duration {
+        let factor = 1u64.checked_shl(self.attempt).unwrap_or(u64::max_value());
+        let delay = cmp::min(self.config.initial_sec.saturating_mul(factor), self.config.max_sec);
+
+        self.attempt = self.attempt.saturating_add(1);
+
+        Duration::from_secs(delay)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
@@ -0,0 +1,129 @@
+//
+// Per host circuit breaker, used to keep `gather` from hammering dead orcas
+// on every poll cycle.
+//
+// closed    - host is polled on every cycle, failures are counted;
+// open      - host is skipped until `open_duration_sec` is elapsed;
+// half-open - single probe request is allowed, success closes breaker,
+//             failure opens it again for another `open_duration_sec`.
+//
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+pub const DEFAULT_OPEN_DURATION_SEC: u64 = 5 * 60;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub open_duration_sec: u64,
+}
+
+impl BreakerConfig {
+    pub fn new() -> BreakerConfig {
+        BreakerConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            open_duration_sec: DEFAULT_OPEN_DURATION_SEC,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostBreaker {
+    pub state: BreakerState,
+    pub failures: u32,
+    pub opened_at: u64,
+    pub last_failure: u64,
+    pub last_error: Option<String>,
+}
+
+impl HostBreaker {
+    pub fn new() -> HostBreaker {
+        HostBreaker {
+            state: BreakerState::Closed,
+            failures: 0,
+            opened_at: 0,
+            last_failure: 0,
+            last_error: None,
+        }
+    }
+}
+
+// mapping: hostname -> breaker
+pub type Breakers = HashMap<String, HostBreaker>;
+pub type SyncedBreakers = RwLock<Arc<Breakers>>;
+
+
+pub trait BreakersTrait {
+    /// Checks whether host should be requested on current cycle, moves
+    /// expired open breakers into half-open state.
+    fn should_probe(&mut self, host: &str, now: u64, config: &BreakerConfig) -> bool;
+
+    fn on_success(&mut self, host: &str);
+    fn on_failure(&mut self, host: &str, now: u64, error: String, config: &BreakerConfig);
+
+    /// Drops breakers of hosts which have left the cluster.
+    fn retain_hosts(&mut self, hosts: &[String]);
+}
+
+impl BreakersTrait for Breakers {
+    fn should_probe(&mut self, host: &str, now: u64, config: &BreakerConfig) -> bool {
+        let breaker = self.entry(host.to_string()).or_insert(HostBreaker::new());
+
+        match breaker.state {
+            BreakerState::Closed | BreakerState::HalfOpen => true,
+            BreakerState::Open =>
+                if now >= breaker.opened_at + config.open_duration_sec {
+                    println!("breaker for host {} is half-open, probing", host);
+                    breaker.state = BreakerState::HalfOpen;
+                    true
+                } else {
+                    false
+                },
+        }
+    }
+
+    fn on_success(&mut self, host: &str) {
+        let breaker = self.entry(host.to_string()).or_insert(HostBreaker::new());
+
+        if breaker.state != BreakerState::Closed {
+            println!("breaker for host {} is closed", host);
+        }
+
+        breaker.state = BreakerState::Closed;
+        breaker.failures = 0;
+    }
+
+    fn on_failure(&mut self, host: &str, now: u64, error: String, config: &BreakerConfig) {
+        let breaker = self.entry(host.to_string()).or_insert(HostBreaker::new());
+
+        breaker.failures += 1;
+        breaker.last_failure = now;
+        breaker.last_error = Some(error);
+
+        let should_open = match breaker.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => breaker.failures >= config.failure_threshold,
+            BreakerState::Open => false,
+        };
+
+        if should_open {
+            println!("breaker for host {} is open after {} failure(s)", host, breaker.failures);
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = now;
+        }
+    }
+
+    fn retain_hosts(&mut self, hosts: &[String]) {
+        self.retain(|host, _| hosts.contains(host));
+    }
+}
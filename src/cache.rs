@@ -0,0 +1,164 @@
+//
+// Serialized web api responses of current snapshot, kept along with their
+// compressed variants until the next snapshot is published. Validators are
+// derived from bodies, so clients revalidate only changed ones.
+//
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use bytes::Bytes;
+use sha1::Sha1;
+
+use flate2::Compression;
+use flate2::write::{DeflateEncoder, GzEncoder};
+
+use hyper::header::{AcceptEncoding, Encoding, q};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BodyEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+impl BodyEncoding {
+    /// Picks the best encoding accepted by client, gzip is preferred.
+    pub fn negotiate(accept: Option<&AcceptEncoding>) -> BodyEncoding {
+        let accepted = |encoding: Encoding| accept.map_or(false, |accept|
+            accept.iter().any(|item| item.item == encoding && item.quality > q(0u16)));
+
+        if accepted(Encoding::Gzip) {
+            BodyEncoding::Gzip
+        } else if accepted(Encoding::Deflate) {
+            BodyEncoding::Deflate
+        } else {
+            BodyEncoding::Identity
+        }
+    }
+
+    // Value of `Content-Encoding` header, if any.
+    pub fn header(&self) -> Option<Encoding> {
+        match *self {
+            BodyEncoding::Identity => None,
+            BodyEncoding::Gzip => Some(Encoding::Gzip),
+            BodyEncoding::Deflate => Some(Encoding::Deflate),
+        }
+    }
+
+    // Variants of the same response differ, so do their entity tags.
+    fn suffix(&self) -> &'static str {
+        match *self {
+            BodyEncoding::Identity => "",
+            BodyEncoding::Gzip => "-gzip",
+            BodyEncoding::Deflate => "-deflate",
+        }
+    }
+}
+
+pub fn compress(data: &[u8], encoding: BodyEncoding) -> Vec<u8> {
+    // Writes into vector can't fail.
+    match encoding {
+        BodyEncoding::Identity => data.to_vec(),
+        BodyEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        },
+        BodyEncoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        },
+    }
+}
+
+// Hex sha1 of body, the same body keeps its tag across snapshots and runs.
+fn digest(body: &[u8]) -> String {
+    let mut sha = Sha1::new();
+    sha.update(body);
+    sha.digest().to_string()
+}
+
+/// Serialized response along with its validators.
+#[derive(Debug, Clone)]
+pub struct CachedBody {
+    pub body: Bytes,
+    // Sha1 of identity body with encoding suffix.
+    pub etag: String,
+    // Time of snapshot body has changed in last, in seconds.
+    pub modified: u64,
+}
+
+pub struct ResponseCache {
+    // Snapshot generation cached bodies are made from.
+    generation: u64,
+    // mapping: (path, encoding) -> body
+    bodies: HashMap<(String, BodyEncoding), CachedBody>,
+    // Kept across snapshots, so unchanged body keeps its modification time.
+    // mapping: path -> (identity body digest, modified)
+    digests: HashMap<String, (String, u64)>,
+}
+
+// Web api is served by single thread, so is the cache.
+pub type SharedResponseCache = Rc<RefCell<ResponseCache>>;
+
+impl ResponseCache {
+    pub fn new() -> ResponseCache {
+        ResponseCache { generation: 0, bodies: HashMap::new(), digests: HashMap::new() }
+    }
+
+    /// Returns cached body of snapshot, `make` is called on miss to
+    /// serialize it, neither `None` nor error from it is cached.
+    pub fn get<F, E>(&mut self, generation: u64, timestamp: u64, path: &str, encoding: BodyEncoding, make: F)
+        -> Result<Option<CachedBody>, E>
+    where
+        F: FnOnce() -> Result<Option<String>, E>
+    {
+        if generation != self.generation {
+            self.bodies.clear();
+            self.generation = generation;
+        }
+
+        if let Some(cached) = self.bodies.get(&(path.to_string(), encoding)) {
+            return Ok(Some(cached.clone()));
+        }
+
+        let identity_key = (path.to_string(), BodyEncoding::Identity);
+        let identity = match self.bodies.get(&identity_key).cloned() {
+            Some(cached) => cached,
+            None => {
+                let body = match make()? {
+                    Some(body) => Bytes::from(body.into_bytes()),
+                    None => return Ok(None),
+                };
+
+                let etag = digest(&body);
+                let modified = match self.digests.get(path) {
+                    Some(&(ref last, modified)) if *last == etag => modified,
+                    _ => timestamp,
+                };
+                self.digests.insert(path.to_string(), (etag.clone(), modified));
+
+                let cached = CachedBody { body, etag, modified };
+                self.bodies.insert(identity_key, cached.clone());
+                cached
+            }
+        };
+
+        if encoding == BodyEncoding::Identity {
+            return Ok(Some(identity));
+        }
+
+        let cached = CachedBody {
+            body: Bytes::from(compress(&identity.body, encoding)),
+            etag: format!("{}{}", identity.etag, encoding.suffix()),
+            modified: identity.modified,
+        };
+        self.bodies.insert((path.to_string(), encoding), cached.clone());
+
+        Ok(Some(cached))
+    }
+}
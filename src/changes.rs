@@ -0,0 +1,65 @@
+//
+// Bounded history of cluster nodes joins, leaves and changes, to correlate
+// orca problems with hosts leaving the cluster.
+//
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Joined,
+    Left,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub timestamp: u64,
+    pub cluster: String,
+    pub uuid: String,
+    pub hostname: String,
+    pub kind: ChangeKind,
+    // Unicorn version of node info, not known for left nodes.
+    pub node_version: Option<i64>,
+    // Version of subscription snapshot change was found in, not known for
+    // periodic refresh and for nodes dropped on subscription loss.
+    pub snapshot_version: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct Changelog {
+    capacity: usize,
+    // Shared with snapshots, copied on the first push after publish.
+    changes: Arc<VecDeque<Change>>,
+}
+
+pub type SyncedChangelog = RwLock<Changelog>;
+
+impl Changelog {
+    pub fn new(capacity: usize) -> Changelog {
+        Changelog {
+            capacity,
+            changes: Arc::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Appends change, the oldest one is dropped if log is full.
+    pub fn push(&mut self, change: Change) {
+        println!("cluster {} node {} ({}) {:?}", change.cluster, change.uuid, change.hostname, change.kind);
+
+        let changes = Arc::make_mut(&mut self.changes);
+        if changes.len() >= self.capacity {
+            changes.pop_front();
+        }
+
+        changes.push_back(change);
+    }
+
+    pub fn changes(&self) -> &Arc<VecDeque<Change>> {
+        &self.changes
+    }
+}
@@ -1,12 +1,39 @@
 use std::path::Path;
 
+use std::env;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
 
+use serde_json::Value;
+
 use yaml_rust::{Yaml, YamlLoader};
+use yaml_rust::yaml::Hash;
 
 use cocaine::service::tvm::Grant;
 
+use access::Role;
+use backoff::BackoffConfig;
+use breaker::BreakerConfig;
+use errors::CombinedError;
+use orca;
+use server;
+
+
+const DEFAULT_TOKEN_SCHEME: &str = "Bearer";
+const DEFAULT_COMMAND_TTL_SEC: i64 = 5 * 60;
+
+const REDACTED: &str = "<redacted>";
+
+// Same as cocaine framework defaults.
+const DEFAULT_LOCATOR_ENDPOINTS: &[&str] = &["[::1]:10053", "127.0.0.1:10053"];
+const DEFAULT_UNICORN_SERVICE: &str = "unicorn";
+const DEFAULT_TVM_SERVICE: &str = "tvm";
+const DEFAULT_HOSTS_FILE_POLL_SEC: u64 = 10;
+const DEFAULT_CORS_MAX_AGE_SEC: u64 = 10 * 60;
+const HTTP_SCHEME: &str = "http";
+const HTTPS_SCHEME: &str = "https";
+
 
 pub const CONFIG_FILES: &[&'static str] = &[
     "/etc/cocaine/.cocaine/tools.yml",
@@ -20,16 +47,188 @@ pub const CONFIG_FILES: &[&'static str] = &[
 pub struct Config {
     pub ticket_expire_sec: Option<i64>,
     pub secure: Option<Secure>,
+    pub breaker: BreakerConfig,
+    pub orca_web: OrcaWeb,
+    pub web: Web,
+    pub clusters: Vec<ClusterSource>,
+    pub locator: Locator,
+    pub subscription: SubscriptionPolicy,
+}
+
+// What to do with cluster nodes when subscription fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnFailure {
+    // Nodes are kept and marked as stale until subscription is restored.
+    Keep,
+    Clear,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionPolicy {
+    pub on_failure: OnFailure,
+    // Stale nodes are cleared after that long, kept forever if not set.
+    pub max_stale_sec: Option<u64>,
+    pub backoff: BackoffConfig,
+}
+
+// Cocaine locator to resolve unicorn and tvm services through.
+#[derive(Debug, Clone)]
+pub struct Locator {
+    // Addresses in form of `host:port`, tried in order of appearance.
+    pub endpoints: Vec<String>,
+    pub unicorn_service: String,
+    pub tvm_service: String,
+}
+
+// Cluster to be monitored, nodes are tagged with cluster name.
+#[derive(Debug, Clone)]
+pub struct ClusterSource {
+    pub name: String,
+    pub discovery: DiscoveryConfig,
+    // Cluster specific `orca` settings, global ones are used for the rest.
+    pub orca: OrcaTransport,
+}
+
+// Where cluster nodes are taken from.
+#[derive(Debug, Clone)]
+pub enum DiscoveryConfig {
+    // Unicorn kids path subscription.
+    Unicorn {
+        kids: String,
+        // Overrides locator endpoints for this cluster, if not empty.
+        locators: Vec<String>,
+    },
+    // Fixed host list from config.
+    Static(Vec<StaticHost>),
+    // Host list file in the same format as static one, reread on change.
+    File { path: String, poll_sec: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticHost {
+    pub hostname: String,
+    // Host is requested by name if address isn't set.
+    pub address: Option<String>,
+    // Overrides `orca_web` port, e.g. for several orcas on one machine.
+    pub port: Option<u16>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Secure {
     pub md: String,
+    pub backend: SecureBackend,
+}
+
+// Credentials source, selected by `mod` field of secure section.
+#[derive(Debug, Clone)]
+pub enum SecureBackend {
+    // mod: TVM
+    Tvm(TvmCredentials),
+    // mod: static, token is reread from file on each request.
+    StaticToken { path: String, scheme: String },
+    // mod: oauth2, client credentials flow.
+    OAuth2(OAuth2Credentials),
+    // mod: command, token is the stdout of credential helper.
+    Command { command: String, args: Vec<String>, scheme: String, ttl_sec: i64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct TvmCredentials {
     pub client_id: i64,
     pub client_secret: String,
     pub grant: Option<Grant>,
 }
 
+#[derive(Debug, Clone)]
+pub struct OAuth2Credentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+    pub scheme: String,
+}
+
+// How orcas web handles should be requested.
+#[derive(Debug, Clone)]
+pub struct OrcaWeb {
+    pub scheme: String,
+    pub port: u16,
+    pub tls: Option<Tls>,
+    pub auth: Option<OrcaAuth>,
+}
+
+// How orcas are connected to, set either globally or per cluster.
+#[derive(Debug, Clone, Default)]
+pub struct OrcaTransport {
+    pub scheme: Option<String>,
+    pub port: Option<u16>,
+    pub tls: Option<Tls>,
+}
+
+// Credentials to be sent with each orca request.
+#[derive(Debug, Clone)]
+pub enum OrcaAuth {
+    // Ticket obtained from secure section service (e.g. TVM).
+    Ticket,
+    Bearer(String),
+    Basic { user: String, password: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Tls {
+    // PEM file with CA certificate(s), system roots are used if not set.
+    pub ca_bundle: Option<String>,
+    // PKCS#12 archive with client certificate and private key.
+    pub client_identity: Option<String>,
+    pub client_identity_password: String,
+    // If set, orca certificate is matched against its hostname, so https
+    // orca is requested by hostname instead of ip address.
+    pub verify_hostname: bool,
+}
+
+// Zorca own web server settings.
+#[derive(Debug, Clone)]
+pub struct Web {
+    // Addresses in form of `ip:port`, `[ip6]:port` or `unix:/path/to.sock`.
+    pub listen: Vec<String>,
+    pub auth: Option<WebAuth>,
+    pub tls: Option<WebTls>,
+    pub cors: Option<WebCors>,
+}
+
+// Cross-origin access to web api, e.g. for dashboard served elsewhere.
+#[derive(Debug, Clone)]
+pub struct WebCors {
+    // Allowed origins, `*` allows any.
+    pub origins: Vec<String>,
+    // If set, browsers send credentials along with cross-origin requests.
+    pub credentials: bool,
+    // How long preflight response could be cached by browser.
+    pub max_age_sec: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebTls {
+    // PEM certificate chain and private key paths.
+    pub cert: String,
+    pub key: String,
+    // If set, clients should present certificate signed by one of these CAs.
+    pub client_ca: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebAuth {
+    // mapping: bearer token -> role
+    pub tokens: HashMap<String, Role>,
+    pub htpasswd: Option<String>,
+    // mapping: htpasswd user -> role, viewer if not listed
+    pub users: HashMap<String, Role>,
+    // Role for requests with the same ticket zorca obtains from secure section.
+    pub ticket_role: Option<Role>,
+    // Role for requests without credentials, denied if not set.
+    pub anonymous_role: Option<Role>,
+}
+
 #[derive(Debug)]
 struct Builder {
     config: Config
@@ -58,15 +257,34 @@ impl Config {
     fn new_with_defaults() -> Config {
         Config{
             ticket_expire_sec: Some(600),
-            secure: None
+            secure: None,
+            breaker: BreakerConfig::new(),
+            orca_web: OrcaWeb::new(),
+            web: Web {
+                listen: vec![ server::DEFAULT_LISTEN_ADDRESS.to_string() ],
+                auth: None,
+                tls: None,
+                cors: None,
+            },
+            clusters: Vec::new(),
+            locator: Locator {
+                endpoints: DEFAULT_LOCATOR_ENDPOINTS.iter().map(|ep| ep.to_string()).collect(),
+                unicorn_service: DEFAULT_UNICORN_SERVICE.to_string(),
+                tvm_service: DEFAULT_TVM_SERVICE.to_string(),
+            },
+            subscription: SubscriptionPolicy {
+                on_failure: OnFailure::Keep,
+                max_stale_sec: None,
+                backoff: BackoffConfig::new(),
+            },
         }
     }
 
-    pub fn new_from_default_files() -> Config {
+    pub fn new_from_default_files() -> Result<Config, CombinedError> {
         Self::new_from_files(CONFIG_FILES)
     }
 
-    pub fn new_from_files(paths: &[&str]) -> Config {
+    pub fn new_from_files(paths: &[&str]) -> Result<Config, CombinedError> {
         let mut builder = Builder::new();
 
         for file in paths {
@@ -79,7 +297,8 @@ impl Config {
                 match extension {
                     "yaml" | "yml" =>
                         if let Some(yaml) = yaml_from_file(file) {
-                            builder.update_from_yaml(yaml);
+                            builder.update_from_yaml(yaml)
+                                .map_err(|e| CombinedError::ConfigError(format!("{}: {}", file, e)))?;
                         },
                     "toml" | "tml" => println!("toml format not implemented: {}", file),
                     _ => println!("unsupported config format: {}", file)
@@ -87,7 +306,189 @@ impl Config {
             }
         }
 
-        builder.build()
+        Ok(builder.build())
+    }
+
+    /// Effective config for diagnostics, secrets are replaced with placeholder.
+    pub fn to_redacted_json(&self) -> Value {
+        let secure = self.secure.as_ref().map(|secure| {
+            let backend = match secure.backend {
+                SecureBackend::Tvm(ref tvm) => json!({
+                    "client_id": tvm.client_id,
+                    "client_secret": REDACTED,
+                    "grant": tvm.grant.as_ref().map(|grant| format!("{:?}", grant)),
+                }),
+                SecureBackend::StaticToken { ref path, ref scheme } => json!({
+                    "path": path,
+                    "scheme": scheme,
+                }),
+                SecureBackend::OAuth2(ref oauth) => json!({
+                    "token_url": oauth.token_url,
+                    "client_id": oauth.client_id,
+                    "client_secret": REDACTED,
+                    "scope": oauth.scope,
+                    "scheme": oauth.scheme,
+                }),
+                // Arguments often carry credentials, so only their number is shown.
+                SecureBackend::Command { ref command, ref args, ref scheme, ttl_sec } => json!({
+                    "command": command,
+                    "args": args.iter().map(|_| REDACTED).collect::<Vec<_>>(),
+                    "scheme": scheme,
+                    "ttl_sec": ttl_sec,
+                }),
+            };
+
+            json!({ "mod": secure.md, "backend": backend })
+        });
+
+        let orca_auth = self.orca_web.auth.as_ref().map(|auth| match *auth {
+            OrcaAuth::Ticket => json!({ "type": "ticket" }),
+            OrcaAuth::Bearer(_) => json!({ "type": "bearer", "token": REDACTED }),
+            OrcaAuth::Basic { ref user, .. } => json!({ "type": "basic", "user": user, "password": REDACTED }),
+        });
+
+        let orca_tls = self.orca_web.tls.as_ref().map(tls_to_redacted_json);
+
+        let web_auth = self.web.auth.as_ref().map(|auth| json!({
+            "tokens": auth.tokens.values().map(|_| REDACTED).collect::<Vec<_>>(),
+            "htpasswd": auth.htpasswd,
+            "users": auth.users,
+            "ticket_role": auth.ticket_role,
+            "anonymous_role": auth.anonymous_role,
+        }));
+
+        let web_tls = self.web.tls.as_ref().map(|tls| json!({
+            "cert": tls.cert,
+            "key": tls.key,
+            "client_ca": tls.client_ca,
+        }));
+
+        let web_cors = self.web.cors.as_ref().map(|cors| json!({
+            "origins": cors.origins,
+            "credentials": cors.credentials,
+            "max_age_sec": cors.max_age_sec,
+        }));
+
+        let clusters: Vec<_> = self.clusters.iter()
+            .map(|source| {
+                let mut cluster = match source.discovery {
+                    DiscoveryConfig::Unicorn { ref kids, ref locators } => json!({
+                        "name": source.name,
+                        "kids": kids,
+                        "locators": locators,
+                    }),
+                    DiscoveryConfig::Static(ref hosts) => json!({
+                        "name": source.name,
+                        "hosts": hosts.iter()
+                            .map(|host| json!({ "hostname": host.hostname, "address": host.address, "port": host.port }))
+                            .collect::<Vec<_>>(),
+                    }),
+                    DiscoveryConfig::File { ref path, poll_sec } => json!({
+                        "name": source.name,
+                        "file": path,
+                        "poll_sec": poll_sec,
+                    }),
+                };
+
+                // Effective settings are shown, so overrides are easy to check.
+                let orca = self.orca_web_for(source);
+                cluster["orca"] = json!({
+                    "scheme": orca.scheme,
+                    "port": orca.port,
+                    "tls": orca.tls.as_ref().map(tls_to_redacted_json),
+                });
+
+                cluster
+            })
+            .collect();
+
+        json!({
+            "ticket_expire_sec": self.ticket_expire_sec,
+            "clusters": clusters,
+            "locator": {
+                "endpoints": self.locator.endpoints,
+                "unicorn_service": self.locator.unicorn_service,
+                "tvm_service": self.locator.tvm_service,
+            },
+            "secure": secure,
+            "subscription": {
+                "on_failure": match self.subscription.on_failure {
+                    OnFailure::Keep => "keep",
+                    OnFailure::Clear => "clear",
+                },
+                "max_stale_sec": self.subscription.max_stale_sec,
+                "backoff_initial_sec": self.subscription.backoff.initial_sec,
+                "backoff_max_sec": self.subscription.backoff.max_sec,
+            },
+            "breaker": {
+                "failure_threshold": self.breaker.failure_threshold,
+                "open_duration_sec": self.breaker.open_duration_sec,
+            },
+            "orca": {
+                "scheme": self.orca_web.scheme,
+                "port": self.orca_web.port,
+                "tls": orca_tls,
+                "auth": orca_auth,
+            },
+            "web": {
+                "listen": self.web.listen,
+                "auth": web_auth,
+                "tls": web_tls,
+                "cors": web_cors,
+            },
+        })
+    }
+}
+
+
+fn tls_to_redacted_json(tls: &Tls) -> Value {
+    json!({
+        "ca_bundle": tls.ca_bundle,
+        "client_identity": tls.client_identity,
+        "client_identity_password": REDACTED,
+        "verify_hostname": tls.verify_hostname,
+    })
+}
+
+
+impl Config {
+    /// Orca settings of cluster, global ones with cluster overrides applied.
+    pub fn orca_web_for(&self, source: &ClusterSource) -> OrcaWeb {
+        let mut web = self.orca_web.clone();
+        web.apply(&source.orca);
+        web
+    }
+}
+
+
+impl Locator {
+    /// Locator with endpoints overridden by cluster ones, if any.
+    pub fn for_cluster(&self, source: &ClusterSource) -> Locator {
+        match source.discovery {
+            DiscoveryConfig::Unicorn { ref locators, .. } if !locators.is_empty() =>
+                Locator { endpoints: locators.clone(), ..self.clone() },
+            _ => self.clone()
+        }
+    }
+}
+
+
+impl ClusterSource {
+    pub fn unicorn(name: &str, kids: &str) -> ClusterSource {
+        ClusterSource {
+            name: name.to_string(),
+            discovery: DiscoveryConfig::Unicorn { kids: kids.to_string(), locators: Vec::new() },
+            orca: OrcaTransport::default(),
+        }
+    }
+
+    // Short human readable description of nodes origin.
+    pub fn describe(&self) -> String {
+        match self.discovery {
+            DiscoveryConfig::Unicorn { ref kids, .. } => format!("unicorn:{}", kids),
+            DiscoveryConfig::Static(ref hosts) => format!("static:{} host(s)", hosts.len()),
+            DiscoveryConfig::File { ref path, .. } => format!("file:{}", path),
+        }
     }
 }
 
@@ -96,6 +497,46 @@ impl Secure {
     pub fn get_mod(&self) -> String {
         self.md.clone()
     }
+
+    // Whether credentials are obtained from external service, so could fail.
+    pub fn issues_tickets(&self) -> bool {
+        match self.backend {
+            SecureBackend::StaticToken { .. } => false,
+            _ => true
+        }
+    }
+}
+
+
+impl OrcaWeb {
+    pub fn new() -> OrcaWeb {
+        OrcaWeb {
+            scheme: orca::DEFAULT_WEB_SCHEME.to_string(),
+            port: orca::DEFAULT_WEB_PORT,
+            tls: None,
+            auth: None,
+        }
+    }
+
+    // Certificate can't be matched against ip address, so https orca is
+    // requested by hostname unless verification is explicitly disabled.
+    pub fn use_hostname(&self) -> bool {
+        self.scheme == HTTPS_SCHEME && self.tls.as_ref().map_or(true, |tls| tls.verify_hostname)
+    }
+
+    fn apply(&mut self, transport: &OrcaTransport) {
+        if let Some(ref scheme) = transport.scheme {
+            self.scheme = scheme.clone();
+        }
+
+        if let Some(port) = transport.port {
+            self.port = port;
+        }
+
+        if let Some(ref tls) = transport.tls {
+            self.tls = Some(tls.clone());
+        }
+    }
 }
 
 
@@ -104,8 +545,8 @@ impl Builder {
         Builder{ config: Config::new_with_defaults() }
     }
 
-    fn add_secure(&mut self, md: String, client_id: i64, client_secret: String, grant: Option<Grant>) -> &mut Self {
-        self.config.secure = Some(Secure{md, client_id, client_secret, grant});
+    fn add_secure(&mut self, md: String, backend: SecureBackend) -> &mut Self {
+        self.config.secure = Some(Secure{md, backend});
         self
     }
 
@@ -113,30 +554,544 @@ impl Builder {
         self.config
     }
 
-    fn update_from_yaml(&mut self, yaml: Vec<Yaml>) -> &Self {
-        fn str_to_yaml(s: &str) -> Yaml {
-            Yaml::from_str(s)
-        }
-
+    fn update_from_yaml(&mut self, yaml: Vec<Yaml>) -> Result<(), String> {
         for yaml in yaml {
             // update secure section
-            yaml.as_hash()
-                .and_then(|tb| tb.get(&str_to_yaml("secure")))
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("secure"))) {
+                Some(&Yaml::Hash(ref tb)) => {
+                    let (md, backend) = parse_secure(tb)
+                        .map_err(|e| format!("secure section: {}", e))?;
+                    self.add_secure(md, backend);
+                },
+                Some(_) => return Err("secure section should be a mapping".to_string()),
+                None => {}
+            };
+
+            // update web section
+            if let Some(tb) = yaml.as_hash()
+                .and_then(|tb| tb.get(&str_to_yaml("web")))
                 .and_then(|tb| tb.as_hash())
-                .and_then::<Yaml,_>(|tb| {
-                    let md = tb.get(&str_to_yaml("mod")).and_then(|v| v.as_str());
-                    let client_id = tb.get(&str_to_yaml("client_id")).and_then(|v| v.as_i64());
-                    let client_secret = tb.get(&str_to_yaml("client_secret")).and_then(|v| v.as_str());
-
-                    if let (Some(md), Some(client_id), Some(client_secret)) = (md, client_id, client_secret) {
-                        let md = String::from(md);
-                        let client_secret = String::from(client_secret);
-                        self.add_secure(md, client_id, client_secret, None);
+            {
+                match tb.get(&str_to_yaml("listen")) {
+                    Some(&Yaml::Array(ref addresses)) => {
+                        self.config.web.listen = addresses.iter()
+                            .map(|address| address.as_str()
+                                .map(String::from)
+                                .ok_or("web `listen` should be a list of addresses"))
+                            .collect::<Result<Vec<_>,_>>()?;
+                    },
+                    Some(&Yaml::String(ref address)) => self.config.web.listen = vec![ address.clone() ],
+                    Some(_) => return Err("web `listen` should be a list of addresses".to_string()),
+                    None => {}
+                };
+
+                match tb.get(&str_to_yaml("auth")) {
+                    Some(&Yaml::Hash(ref auth)) => {
+                        let auth = parse_web_auth(auth)
+                            .map_err(|e| format!("web auth section: {}", e))?;
+                        self.config.web.auth = Some(auth);
+                    },
+                    Some(_) => return Err("web auth section should be a mapping".to_string()),
+                    None => {}
+                };
+
+                match tb.get(&str_to_yaml("tls")) {
+                    Some(&Yaml::Hash(ref tls)) => {
+                        let cert = get_str(tls, "cert")
+                            .ok_or("web tls section: `cert` is required")?;
+                        let key = get_str(tls, "key")
+                            .ok_or("web tls section: `key` is required")?;
+                        let client_ca = get_str(tls, "client_ca");
+
+                        self.config.web.tls = Some(WebTls { cert, key, client_ca });
+                    },
+                    Some(_) => return Err("web tls section should be a mapping".to_string()),
+                    None => {}
+                };
+
+                match tb.get(&str_to_yaml("cors")) {
+                    Some(&Yaml::Hash(ref cors)) => {
+                        let cors = parse_web_cors(cors)
+                            .map_err(|e| format!("web cors section: {}", e))?;
+                        self.config.web.cors = Some(cors);
+                    },
+                    Some(_) => return Err("web cors section should be a mapping".to_string()),
+                    None => {}
+                };
+            }
+
+            // update clusters section
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("clusters"))) {
+                Some(&Yaml::Array(ref clusters)) => {
+                    self.config.clusters = parse_clusters(clusters)
+                        .map_err(|e| format!("clusters section: {}", e))?;
+                },
+                Some(_) => return Err("clusters section should be a list".to_string()),
+                None => {}
+            };
+
+            // update locator section
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("locator"))) {
+                Some(&Yaml::Hash(ref tb)) => {
+                    match tb.get(&str_to_yaml("endpoints")) {
+                        Some(&Yaml::Array(ref endpoints)) => {
+                            let endpoints = parse_locators(endpoints)
+                                .map_err(|e| format!("locator section: {}", e))?;
+                            if endpoints.is_empty() {
+                                return Err("locator section: `endpoints` should not be empty".to_string());
+                            }
+                            self.config.locator.endpoints = endpoints;
+                        },
+                        Some(_) => return Err("locator `endpoints` should be a list".to_string()),
+                        None => {}
                     };
 
-                    None
-                });
+                    if let Some(name) = get_str(tb, "unicorn_service") {
+                        self.config.locator.unicorn_service = name;
+                    }
+
+                    if let Some(name) = get_str(tb, "tvm_service") {
+                        self.config.locator.tvm_service = name;
+                    }
+                },
+                Some(_) => return Err("locator section should be a mapping".to_string()),
+                None => {}
+            };
+
+            // update subscription section
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("subscription"))) {
+                Some(&Yaml::Hash(ref tb)) => {
+                    match get_str(tb, "on_failure").as_ref().map(|policy| &policy[..]) {
+                        Some("keep") => self.config.subscription.on_failure = OnFailure::Keep,
+                        Some("clear") => self.config.subscription.on_failure = OnFailure::Clear,
+                        Some(policy) => return Err(format!("unknown subscription `on_failure` policy {}", policy)),
+                        None => {}
+                    };
+
+                    match tb.get(&str_to_yaml("max_stale_sec")) {
+                        Some(&Yaml::Integer(max_stale)) if max_stale >= 0 =>
+                            self.config.subscription.max_stale_sec = Some(max_stale as u64),
+                        Some(_) => return Err("subscription `max_stale_sec` should be a non-negative number".to_string()),
+                        None => {}
+                    };
+
+                    match tb.get(&str_to_yaml("backoff_initial_sec")) {
+                        Some(&Yaml::Integer(initial)) if initial > 0 =>
+                            self.config.subscription.backoff.initial_sec = initial as u64,
+                        Some(_) => return Err("subscription `backoff_initial_sec` should be a positive number".to_string()),
+                        None => {}
+                    };
+
+                    match tb.get(&str_to_yaml("backoff_max_sec")) {
+                        Some(&Yaml::Integer(max)) if max > 0 =>
+                            self.config.subscription.backoff.max_sec = max as u64,
+                        Some(_) => return Err("subscription `backoff_max_sec` should be a positive number".to_string()),
+                        None => {}
+                    };
+
+                    let backoff = &self.config.subscription.backoff;
+                    if backoff.max_sec < backoff.initial_sec {
+                        return Err("subscription `backoff_max_sec` should not be less than `backoff_initial_sec`".to_string());
+                    }
+                },
+                Some(_) => return Err("subscription section should be a mapping".to_string()),
+                None => {}
+            };
+
+            // update breaker section
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("breaker"))) {
+                Some(&Yaml::Hash(ref tb)) => {
+                    match tb.get(&str_to_yaml("failure_threshold")) {
+                        Some(&Yaml::Integer(threshold)) if threshold > 0 && threshold <= u32::max_value() as i64 =>
+                            self.config.breaker.failure_threshold = threshold as u32,
+                        Some(_) => return Err("breaker `failure_threshold` should be a positive number".to_string()),
+                        None => {}
+                    };
+
+                    match tb.get(&str_to_yaml("open_duration_sec")) {
+                        Some(&Yaml::Integer(duration)) if duration > 0 =>
+                            self.config.breaker.open_duration_sec = duration as u64,
+                        Some(_) => return Err("breaker `open_duration_sec` should be a positive number".to_string()),
+                        None => {}
+                    };
+                },
+                Some(_) => return Err("breaker section should be a mapping".to_string()),
+                None => {}
+            };
+
+            // update orca web section
+            match yaml.as_hash().and_then(|tb| tb.get(&str_to_yaml("orca"))) {
+                Some(&Yaml::Hash(ref tb)) => {
+                    let transport = parse_orca_transport(tb)
+                        .map_err(|e| format!("orca section: {}", e))?;
+                    self.config.orca_web.apply(&transport);
+
+                    match tb.get(&str_to_yaml("auth")) {
+                        Some(&Yaml::Hash(ref auth)) => {
+                            let auth = parse_orca_auth(auth)
+                                .map_err(|e| format!("orca auth: {}", e))?;
+                            self.config.orca_web.auth = Some(auth);
+                        },
+                        Some(_) => return Err("orca auth: section should be a mapping".to_string()),
+                        None => {}
+                    };
+                },
+                Some(_) => return Err("orca section should be a mapping".to_string()),
+                None => {}
+            };
         } // for yaml in yaml::Array
-        self
+
+        Ok(())
+    }
+}
+
+
+fn str_to_yaml(s: &str) -> Yaml {
+    Yaml::from_str(s)
+}
+
+fn get_str(tb: &Hash, key: &str) -> Option<String> {
+    tb.get(&str_to_yaml(key)).and_then(|v| v.as_str()).map(String::from)
+}
+
+fn parse_orca_auth(tb: &Hash) -> Result<OrcaAuth, String> {
+    let auth = match get_str(tb, "type").as_ref().map(|ty| &ty[..]) {
+        Some("ticket") => OrcaAuth::Ticket,
+        Some("bearer") => OrcaAuth::Bearer(get_str(tb, "token").ok_or("`token` is required for bearer type")?),
+        Some("basic") => OrcaAuth::Basic {
+            user: get_str(tb, "user").ok_or("`user` is required for basic type")?,
+            password: get_str(tb, "password").ok_or("`password` is required for basic type")?,
+        },
+        Some(ty) => return Err(format!("unknown `type` {}, expected one of ticket, bearer or basic", ty)),
+        None => return Err("`type` is required".to_string()),
+    };
+
+    Ok(auth)
+}
+
+fn parse_orca_transport(tb: &Hash) -> Result<OrcaTransport, String> {
+    let scheme = get_str(tb, "scheme");
+    match scheme.as_ref().map(|scheme| &scheme[..]) {
+        None | Some(HTTP_SCHEME) | Some(HTTPS_SCHEME) => {},
+        Some(scheme) => return Err(format!("unsupported `scheme` {}, should be http or https", scheme))
+    };
+
+    let port = match tb.get(&str_to_yaml("port")) {
+        Some(&Yaml::Integer(port)) if port > 0 && port <= u16::max_value() as i64 => Some(port as u16),
+        Some(_) => return Err("`port` should be a valid port number".to_string()),
+        None => None
+    };
+
+    let tls = match tb.get(&str_to_yaml("tls")) {
+        Some(&Yaml::Hash(ref tls)) => {
+            let verify_hostname = tls.get(&str_to_yaml("verify_hostname"))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+
+            Some(Tls {
+                ca_bundle: get_str(tls, "ca_bundle"),
+                client_identity: get_str(tls, "client_identity"),
+                client_identity_password: get_str(tls, "client_identity_password").unwrap_or_default(),
+                verify_hostname,
+            })
+        },
+        Some(_) => return Err("tls section should be a mapping".to_string()),
+        None => None
+    };
+
+    Ok(OrcaTransport { scheme, port, tls })
+}
+
+// Only form is checked here, names are resolved on each connection attempt.
+fn parse_locators(addresses: &[Yaml]) -> Result<Vec<String>, String> {
+    addresses.iter()
+        .map(|address| match address.as_str() {
+            Some(address) if address.rfind(':').map_or(false, |pos| address[pos + 1..].parse::<u16>().is_ok()) =>
+                Ok(address.to_string()),
+            Some(address) => Err(format!("locator address {} should be in form of host:port", address)),
+            None => Err("locator should be an address string".to_string())
+        })
+        .collect()
+}
+
+// Host is either a hostname string or a mapping with `hostname` and
+// optional `address` fields.
+pub fn parse_static_hosts(hosts: &[Yaml]) -> Result<Vec<StaticHost>, String> {
+    hosts.iter()
+        .map(|host| match *host {
+            Yaml::String(ref hostname) => Ok(StaticHost { hostname: hostname.clone(), address: None, port: None }),
+            Yaml::Hash(ref tb) => {
+                let hostname = get_str(tb, "hostname")
+                    .ok_or("host `hostname` is required")?;
+                let port = match tb.get(&str_to_yaml("port")) {
+                    Some(&Yaml::Integer(port)) if port > 0 && port <= u16::max_value() as i64 => Some(port as u16),
+                    Some(_) => return Err(format!("host {}: `port` should be a valid port number", hostname)),
+                    None => None
+                };
+                Ok(StaticHost { hostname, address: get_str(tb, "address"), port })
+            },
+            _ => Err("host should be a hostname or a mapping".to_string())
+        })
+        .collect()
+}
+
+/// Reads host list file, which is a yaml list of hosts.
+pub fn load_hosts_file(path: &str) -> Result<Vec<StaticHost>, String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("failed to read hosts file {}: {}", path, e))?;
+
+    let yaml = YamlLoader::load_from_str(&content)
+        .map_err(|e| format!("failed to parse hosts file {}: {}", path, e))?;
+
+    match yaml.first() {
+        Some(&Yaml::Array(ref hosts)) => parse_static_hosts(hosts),
+        // Empty file is an empty list.
+        None => Ok(Vec::new()),
+        Some(_) => Err(format!("hosts file {} should contain a list", path))
+    }
+}
+
+fn parse_discovery(tb: &Hash) -> Result<DiscoveryConfig, String> {
+    let kids = get_str(tb, "kids");
+    let hosts = tb.get(&str_to_yaml("hosts"));
+    let file = get_str(tb, "file");
+
+    match (kids, hosts, file) {
+        (Some(kids), None, None) => {
+            let locators = match tb.get(&str_to_yaml("locators")) {
+                Some(&Yaml::Array(ref addresses)) => parse_locators(addresses)?,
+                Some(_) => return Err("`locators` should be a list".to_string()),
+                None => Vec::new()
+            };
+            Ok(DiscoveryConfig::Unicorn { kids, locators })
+        },
+        (None, Some(&Yaml::Array(ref hosts)), None) => Ok(DiscoveryConfig::Static(parse_static_hosts(hosts)?)),
+        (None, Some(_), None) => Err("`hosts` should be a list".to_string()),
+        (None, None, Some(path)) => {
+            let poll_sec = match tb.get(&str_to_yaml("poll_sec")).and_then(|v| v.as_i64()) {
+                Some(sec) if sec > 0 => sec as u64,
+                Some(_) => return Err("`poll_sec` should be positive".to_string()),
+                None => DEFAULT_HOSTS_FILE_POLL_SEC
+            };
+            Ok(DiscoveryConfig::File { path, poll_sec })
+        },
+        _ => Err("exactly one of `kids`, `hosts` or `file` should be set".to_string())
+    }
+}
+
+fn parse_clusters(clusters: &[Yaml]) -> Result<Vec<ClusterSource>, String> {
+    let mut names = HashSet::new();
+    let mut sources = Vec::with_capacity(clusters.len());
+
+    for cluster in clusters {
+        let tb = cluster.as_hash()
+            .ok_or("cluster record should be a mapping")?;
+
+        let discovery = parse_discovery(tb)?;
+        let name = match (get_str(tb, "name"), &discovery) {
+            (Some(name), _) => name,
+            (None, &DiscoveryConfig::Unicorn { ref kids, .. }) => kids.clone(),
+            (None, _) => return Err("`name` is required for non unicorn cluster".to_string())
+        };
+
+        if !names.insert(name.clone()) {
+            return Err(format!("duplicate cluster name {}", name));
+        }
+
+        let orca = match tb.get(&str_to_yaml("orca")) {
+            Some(&Yaml::Hash(ref orca)) => parse_orca_transport(orca)
+                .map_err(|e| format!("cluster {} orca section: {}", name, e))?,
+            Some(_) => return Err(format!("cluster {} orca section should be a mapping", name)),
+            None => OrcaTransport::default()
+        };
+
+        sources.push(ClusterSource { name, discovery, orca });
+    }
+
+    Ok(sources)
+}
+
+fn parse_secure(tb: &Hash) -> Result<(String, SecureBackend), String> {
+    let md = get_str(tb, "mod")
+        .ok_or("`mod` is required")?;
+
+    let scheme = get_str(tb, "scheme").unwrap_or(DEFAULT_TOKEN_SCHEME.to_string());
+
+    let backend = match &md[..] {
+        "TVM" => SecureBackend::Tvm(parse_tvm(tb)?),
+        "static" => {
+            let path = get_str(tb, "token_file")
+                .ok_or("`token_file` is required for static mod")?;
+            SecureBackend::StaticToken { path, scheme }
+        },
+        "oauth2" => {
+            let token_url = get_str(tb, "token_url")
+                .ok_or("`token_url` is required for oauth2 mod")?;
+            let client_id = match tb.get(&str_to_yaml("client_id")) {
+                Some(&Yaml::String(ref id)) => id.clone(),
+                Some(&Yaml::Integer(id)) => id.to_string(),
+                _ => return Err("`client_id` is required for oauth2 mod".to_string()),
+            };
+            let client_secret = parse_client_secret(tb)?;
+            let scope = get_str(tb, "scope");
+
+            SecureBackend::OAuth2(OAuth2Credentials { token_url, client_id, client_secret, scope, scheme })
+        },
+        "command" => {
+            let command = get_str(tb, "command")
+                .ok_or("`command` is required for command mod")?;
+            let args = match tb.get(&str_to_yaml("args")) {
+                Some(&Yaml::Array(ref args)) => args.iter()
+                    .map(|arg| arg.as_str().map(String::from).ok_or("`args` should be a list of strings"))
+                    .collect::<Result<Vec<_>,_>>()?,
+                Some(_) => return Err("`args` should be a list of strings".to_string()),
+                None => Vec::new(),
+            };
+            let ttl_sec = match tb.get(&str_to_yaml("ttl_sec")) {
+                Some(&Yaml::Integer(ttl)) if ttl > 0 => ttl,
+                Some(_) => return Err("`ttl_sec` should be a positive number".to_string()),
+                None => DEFAULT_COMMAND_TTL_SEC,
+            };
+
+            SecureBackend::Command { command, args, scheme, ttl_sec }
+        },
+        _ => return Err(format!("unknown mod `{}`, expected one of TVM, static, oauth2 or command", md)),
+    };
+
+    Ok((md, backend))
+}
+
+fn parse_tvm(tb: &Hash) -> Result<TvmCredentials, String> {
+    let client_id = match tb.get(&str_to_yaml("client_id")) {
+        Some(&Yaml::Integer(id)) => id,
+        Some(_) => return Err("`client_id` should be an integer".to_string()),
+        None => return Err("`client_id` is required".to_string()),
+    };
+
+    let client_secret = parse_client_secret(tb)?;
+
+    let grant = match tb.get(&str_to_yaml("grant")) {
+        Some(&Yaml::Hash(ref grant)) => Some(parse_grant(grant)?),
+        Some(&Yaml::String(ref ty)) => {
+            let mut grant = Hash::new();
+            grant.insert(str_to_yaml("type"), Yaml::String(ty.clone()));
+            Some(parse_grant(&grant)?)
+        },
+        Some(_) => return Err("`grant` should be a grant type or a mapping".to_string()),
+        None => None,
+    };
+
+    Ok(TvmCredentials { client_id, client_secret, grant })
+}
+
+fn parse_role(role: &Yaml) -> Result<Role, String> {
+    role.as_str()
+        .and_then(Role::from_str)
+        .ok_or(format!("unknown role {:?}, expected viewer or admin", role))
+}
+
+fn parse_roles(tb: &Hash, key: &str) -> Result<HashMap<String, Role>, String> {
+    let mut roles = HashMap::new();
+
+    match tb.get(&str_to_yaml(key)) {
+        Some(&Yaml::Hash(ref items)) => for (name, role) in items {
+            let name = name.as_str()
+                .ok_or(format!("`{}` keys should be strings", key))?;
+            roles.insert(name.to_string(), parse_role(role)?);
+        },
+        Some(_) => return Err(format!("`{}` should be a mapping", key)),
+        None => {}
+    };
+
+    Ok(roles)
+}
+
+fn parse_web_auth(tb: &Hash) -> Result<WebAuth, String> {
+    let optional_role = |key: &str| match tb.get(&str_to_yaml(key)) {
+        Some(role) => parse_role(role).map(Some),
+        None => Ok(None)
+    };
+
+    Ok(WebAuth {
+        tokens: parse_roles(tb, "tokens")?,
+        htpasswd: get_str(tb, "htpasswd"),
+        users: parse_roles(tb, "users")?,
+        ticket_role: optional_role("ticket")?,
+        anonymous_role: optional_role("anonymous")?,
+    })
+}
+
+fn parse_web_cors(tb: &Hash) -> Result<WebCors, String> {
+    let origins = match tb.get(&str_to_yaml("origins")) {
+        Some(&Yaml::Array(ref origins)) => origins.iter()
+            .map(|origin| origin.as_str()
+                .map(String::from)
+                .ok_or("`origins` should be a list of origins"))
+            .collect::<Result<Vec<_>,_>>()?,
+        Some(&Yaml::String(ref origin)) => vec![ origin.clone() ],
+        Some(_) => return Err("`origins` should be a list of origins".to_string()),
+        None => return Err("`origins` is required".to_string()),
+    };
+
+    let credentials = match tb.get(&str_to_yaml("credentials")) {
+        Some(&Yaml::Boolean(credentials)) => credentials,
+        Some(_) => return Err("`credentials` should be a boolean".to_string()),
+        None => false
+    };
+
+    // Browsers refuse credentials for wildcard origin anyway.
+    if credentials && origins.iter().any(|origin| origin == "*") {
+        return Err("`credentials` can't be allowed for `*` origin".to_string());
+    }
+
+    let max_age_sec = match tb.get(&str_to_yaml("max_age_sec")) {
+        Some(&Yaml::Integer(max_age)) if max_age >= 0 => max_age as u64,
+        Some(_) => return Err("`max_age_sec` should be a non-negative number".to_string()),
+        None => DEFAULT_CORS_MAX_AGE_SEC
+    };
+
+    Ok(WebCors { origins, credentials, max_age_sec })
+}
+
+// Secret could be set inline, read from file or taken from environment,
+// exactly one of the sources should be specified.
+fn parse_client_secret(tb: &Hash) -> Result<String, String> {
+    let inline = get_str(tb, "client_secret");
+    let file = get_str(tb, "client_secret_file");
+    let var = get_str(tb, "client_secret_env");
+
+    match (inline, file, var) {
+        (Some(secret), None, None) => Ok(secret),
+        (None, Some(path), None) => {
+            let mut content = String::new();
+            File::open(&path)
+                .and_then(|mut fl| fl.read_to_string(&mut content))
+                .map_err(|e| format!("failed to read `client_secret_file` {}: {}", path, e))?;
+
+            Ok(content.trim().to_string())
+        },
+        (None, None, Some(var)) => env::var(&var)
+            .map_err(|e| format!("failed to read `client_secret_env` variable {}: {}", var, e)),
+        (None, None, None) =>
+            Err("one of `client_secret`, `client_secret_file` or `client_secret_env` is required".to_string()),
+        _ =>
+            Err("only one of `client_secret`, `client_secret_file` or `client_secret_env` should be set".to_string()),
+    }
+}
+
+fn parse_grant(tb: &Hash) -> Result<Grant, String> {
+    let ty = get_str(tb, "type")
+        .ok_or("grant `type` is required")?;
+
+    match &ty[..] {
+        "client_credentials" => Ok(Grant::ClientCredentials),
+        "ticket_exchange" => {
+            let ticket = get_str(tb, "ticket")
+                .ok_or("`ticket` is required for ticket_exchange grant")?;
+            Ok(Grant::TicketExchange { ticket })
+        },
+        _ => Err(format!("unknown grant type `{}`, expected client_credentials or ticket_exchange", ty)),
     }
 }
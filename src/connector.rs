@@ -0,0 +1,106 @@
+//
+// Construction of http(s) client used to request orcas web handles.
+//
+use std::fs::File;
+use std::io::Read;
+
+use tokio_core::reactor::Handle;
+
+use hyper::client::{Client, HttpConnector};
+use hyper_tls::HttpsConnector;
+use native_tls::{Certificate, Pkcs12, TlsConnector};
+
+use config::{OrcaWeb, Tls};
+use errors::CombinedError;
+
+
+const DNS_WORKER_THREADS: usize = 4;
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+// Note: https connector passes plain `http` uris through as is, so single
+//       client type is suitable for both schemes.
+pub type OrcaConnector = HttpsConnector<HttpConnector>;
+pub type OrcaClient = Client<OrcaConnector>;
+
+
+pub fn make_client(handle: &Handle, config: &OrcaWeb) -> Result<OrcaClient, CombinedError> {
+    let connector = make_connector(handle, config)?;
+
+    let client = Client::configure()
+        .connector(connector)
+        .build(handle);
+
+    Ok(client)
+}
+
+// Client with system trust roots, used for auxiliary services (e.g. token endpoints).
+pub fn make_default_client(handle: &Handle) -> Result<OrcaClient, CombinedError> {
+    make_client(handle, &OrcaWeb::new())
+}
+
+pub fn make_connector(handle: &Handle, config: &OrcaWeb) -> Result<OrcaConnector, CombinedError> {
+    let mut http = HttpConnector::new(DNS_WORKER_THREADS, handle);
+    http.enforce_http(false);
+
+    let tls = match config.tls {
+        Some(ref tls) => make_tls_connector(tls)?,
+        None => TlsConnector::builder()?.build()?
+    };
+
+    let mut connector = HttpsConnector::from((http, tls));
+
+    if let Some(ref tls) = config.tls {
+        connector.danger_disable_hostname_verification(!tls.verify_hostname);
+    }
+
+    Ok(connector)
+}
+
+fn make_tls_connector(config: &Tls) -> Result<TlsConnector, CombinedError> {
+    let mut builder = TlsConnector::builder()?;
+
+    if let Some(ref path) = config.ca_bundle {
+        for cert in read_pem_bundle(path)? {
+            builder.add_root_certificate(cert)?;
+        }
+    }
+
+    if let Some(ref path) = config.client_identity {
+        let der = read_file(path)?;
+        let identity = Pkcs12::from_der(&der, &config.client_identity_password)?;
+        builder.identity(identity)?;
+    }
+
+    let connector = builder.build()?;
+    Ok(connector)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, CombinedError> {
+    let mut content = Vec::new();
+    File::open(path)?.read_to_end(&mut content)?;
+
+    Ok(content)
+}
+
+// Splits PEM bundle (as found in /etc/ssl/certs/ca-certificates.crt) into
+// separate certificates.
+fn read_pem_bundle(path: &str) -> Result<Vec<Certificate>, CombinedError> {
+    let content = String::from_utf8(read_file(path)?)
+        .map_err(|e| CombinedError::Other(format!("CA bundle {} is not a text file: {}", path, e)))?;
+
+    let mut certs = Vec::new();
+    for chunk in content.split_terminator(PEM_CERT_END) {
+        if chunk.trim().is_empty() {
+            continue;
+        }
+
+        let pem = format!("{}{}\n", chunk.trim_left(), PEM_CERT_END);
+        certs.push(Certificate::from_pem(pem.as_bytes())?);
+    }
+
+    if certs.is_empty() {
+        return Err(CombinedError::Other(format!("no certificates found in CA bundle {}", path)));
+    }
+
+    Ok(certs)
+}
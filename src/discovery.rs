@@ -0,0 +1,198 @@
+//
+// Sources of cluster nodes: unicorn subscription, static and file host lists.
+//
+use std::fs;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use futures::future;
+
+use tokio_core::reactor::{Handle, Interval};
+
+use config::{ClusterSource, DiscoveryConfig, Locator, StaticHost, load_hosts_file};
+use engine::{ClusterView, replace_nodes, subscription};
+use errors::CombinedError;
+use health::{Subsystem, SyncedHealth, now_secs};
+use locator::{make_resolved_service, probe_resolved, resolve_async};
+use resources::{Endpoint, NodeInfo, Resources};
+use secure::SharedProxy;
+use snapshot::Publisher;
+
+
+// Static hosts don't announce ports, orca port is taken from `orca_web`
+// config on gather anyway.
+const STATIC_ENDPOINT_PORT: u16 = 0;
+
+pub type DiscoveryFuture = Box<Future<Item=(), Error=CombinedError>>;
+
+/// Keeps cluster view nodes up to date.
+pub trait Discovery {
+    // Makes future resolved when source fails, view is left as is on exit,
+    // so stale policy is up to caller, as well as cancellation on shutdown.
+    fn run(&self, handle: &Handle, view: &ClusterView) -> DiscoveryFuture;
+}
+
+pub struct UnicornDiscovery {
+    name: String,
+    kids: String,
+    locator: Locator,
+    proxy: SharedProxy,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher,
+}
+
+impl Discovery for UnicornDiscovery {
+    fn run(&self, handle: &Handle, view: &ClusterView) -> DiscoveryFuture {
+        let handle = handle.clone();
+        let (locator, name, kids) = (self.locator.clone(), self.name.clone(), self.kids.clone());
+        let view = view.clone();
+        let proxy = Rc::clone(&self.proxy);
+        let health = Arc::clone(&self.health);
+        let publisher = self.publisher.clone();
+
+        // Locator is resolved once per subscription, off the reactor, then
+        // checked first, so its failures are reported apart.
+        let work = resolve_async(&self.locator)
+            .and_then(move |addrs| {
+                let unicorn = make_resolved_service(&locator.unicorn_service, &handle, &addrs)?;
+                Ok(probe_resolved(&handle, &locator, addrs.clone())
+                    .and_then(move |addr| {
+                        println!("locator {} is reachable for cluster {}", addr, name);
+                        subscription(unicorn, handle, &name, &kids, &locator, addrs, view, proxy, health, publisher)
+                    }))
+            })
+            .flatten();
+
+        Box::new(work)
+    }
+}
+
+pub struct StaticDiscovery {
+    name: String,
+    hosts: Vec<StaticHost>,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher,
+}
+
+impl Discovery for StaticDiscovery {
+    fn run(&self, _handle: &Handle, view: &ClusterView) -> DiscoveryFuture {
+        replace_nodes(view, &self.name, make_nodes(&self.name, &self.hosts), now_secs());
+        self.publisher.publish_nodes(&self.name);
+        self.health.write().unwrap().idle(&Subsystem::Subscription(self.name.clone()));
+
+        // Nothing to watch, never fails.
+        Box::new(future::empty())
+    }
+}
+
+pub struct FileDiscovery {
+    name: String,
+    path: String,
+    poll_sec: u64,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher,
+}
+
+impl Discovery for FileDiscovery {
+    fn run(&self, handle: &Handle, view: &ClusterView) -> DiscoveryFuture {
+        let interval = match Interval::new(Duration::from_secs(self.poll_sec), handle) {
+            Ok(interval) => interval,
+            Err(e) => return Box::new(future::err(CombinedError::IOError(e)))
+        };
+
+        let subsystem = Subsystem::Subscription(self.name.clone());
+        let (name, path) = (self.name.clone(), self.path.clone());
+        let view = view.clone();
+        let health = Arc::clone(&self.health);
+        let publisher = self.publisher.clone();
+        let mut last_modified = None;
+
+        // First tick is fired right away, so file is read on start.
+        let polling = interval
+            .map_err(CombinedError::IOError)
+            .for_each(move |_| {
+                let modified = fs::metadata(&path).and_then(|meta| meta.modified());
+                let modified = match modified {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        let error = format!("failed to stat hosts file {}: {}", path, e);
+                        println!("{}", error);
+                        health.write().unwrap().failure(&subsystem, error);
+                        return Ok(());
+                    }
+                };
+
+                if last_modified == Some(modified) {
+                    health.write().unwrap().success(&subsystem);
+                    return Ok(());
+                }
+
+                // Broken file keeps previous nodes, it is reread on next change.
+                match load_hosts_file(&path) {
+                    Ok(hosts) => {
+                        println!("hosts file {} has {} host(s) for cluster {}", path, hosts.len(), name);
+                        replace_nodes(&view, &name, make_nodes(&name, &hosts), now_secs());
+                        publisher.publish_nodes(&name);
+                        health.write().unwrap().success(&subsystem);
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                        health.write().unwrap().failure(&subsystem, e);
+                    }
+                }
+
+                last_modified = Some(modified);
+                Ok(())
+            });
+
+        Box::new(polling)
+    }
+}
+
+pub fn make_discovery(
+    source: &ClusterSource,
+    locator: Locator,
+    proxy: SharedProxy,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher)
+    -> Box<Discovery>
+{
+    let name = source.name.clone();
+
+    match source.discovery {
+        DiscoveryConfig::Unicorn { ref kids, .. } =>
+            Box::new(UnicornDiscovery { name, kids: kids.clone(), locator, proxy, health, publisher }),
+        DiscoveryConfig::Static(ref hosts) =>
+            Box::new(StaticDiscovery { name, hosts: hosts.clone(), health, publisher }),
+        DiscoveryConfig::File { ref path, poll_sec } =>
+            Box::new(FileDiscovery { name, path: path.clone(), poll_sec, health, publisher }),
+    }
+}
+
+// Hostname is used as uuid, as there is no other identity for static hosts.
+fn make_nodes(cluster: &str, hosts: &[StaticHost]) -> Vec<(String, NodeInfo)> {
+    hosts.iter()
+        .map(|host| {
+            let info = NodeInfo {
+                hostname: host.hostname.clone(),
+                resources: Resources { cpu: 0, mem: 0 },
+                endpoints: make_endpoints(host),
+                cluster: cluster.to_string(),
+                unicorn_version: None,
+                stale_since: None,
+                orca_port: host.port,
+            };
+            (host.hostname.clone(), info)
+        })
+        .collect()
+}
+
+fn make_endpoints(host: &StaticHost) -> Vec<Endpoint> {
+    // No dns lookups here, host without address is requested by name on
+    // gather, so the name is resolved by http client off the reactor.
+    host.address.iter()
+        .map(|address| Endpoint(address.clone(), STATIC_ENDPOINT_PORT))
+        .collect()
+}
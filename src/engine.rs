@@ -1,10 +1,10 @@
 use cocaine::Service;
-use cocaine::service::Unicorn;
 use cocaine::hpack::RawHeader;
 
-use tokio_core::reactor::{Handle, Timeout};
+use tokio_core::reactor::{Handle, Interval, Timeout};
 
 use hyper;
+use base64;
 
 use futures::future;
 use futures::{Future, Stream};
@@ -21,21 +21,32 @@ use std::time::{self, UNIX_EPOCH};
 use std::iter::Iterator;
 use std::net;
 use std::collections::{
+    BTreeMap,
     HashMap,
     BTreeSet
 };
 
-use secure::make_ticket_service;
+use secure::{SecureProxy, SharedProxy};
 use errors::CombinedError;
-use config::Config;
+use config::{Locator, OrcaAuth, OrcaWeb};
+use locator::make_resolved_service;
 use resources::{Endpoint, NodeInfo};
 
 use unicorn::{
     kids_subscribe,
-    get_node
+    get_node,
 };
 
 use orca;
+use health::{Subsystem, SyncedHealth};
+use snapshot::Publisher;
+use changes::{self, Change, ChangeKind, Changelog, SyncedChangelog};
+use breaker::{
+    BreakerConfig,
+    Breakers,
+    BreakersTrait,
+    SyncedBreakers,
+};
 
 // Note: in case of massive cluster updates (score of machines was restarted),
 //       it could be quite massive subscription update rate, so channel queue size
@@ -48,33 +59,75 @@ const GATHER_INTERVAL_SECS: u64 = 30;
 const ONE_HOUR_IN_SECS: u64 = 1 * 60 * 60;
 const SPOILED_ORCA_EXPIRATION_SEC: u64 = ONE_HOUR_IN_SECS / 2;
 
+// Already known nodes are refetched that often in case their info has
+// changed without change of kids list.
+const NODE_REFRESH_SEC: u64 = 10 * 60;
+const NODE_REFRESH_CHECK_SEC: u64 = 60;
+
 pub type SubscribeMessage = (i64, Vec<String>);
 
 pub type Cluster = HashMap<String, NodeInfo>;
-pub type SyncedCluster = RwLock<Cluster>;
+pub type SyncedCluster = RwLock<Arc<Cluster>>;
+
+// State of single monitored cluster, set of clusters is fixed on startup.
+#[derive(Clone)]
+pub struct ClusterView {
+    pub cluster: Arc<SyncedCluster>,
+    pub orcas: Arc<orca::SyncedOrcasPod>,
+    pub apps: Arc<orca::SyncedApps>,
+    pub breakers: Arc<SyncedBreakers>,
+    pub changes: Arc<SyncedChangelog>,
+}
+
+// mapping: cluster name -> view
+pub type ClusterViews = BTreeMap<String, ClusterView>;
+
+impl ClusterView {
+    pub fn new() -> ClusterView {
+        ClusterView::with_cluster(Cluster::new())
+    }
+
+    pub fn with_cluster(cluster: Cluster) -> ClusterView {
+        ClusterView {
+            cluster: Arc::new(SyncedCluster::new(Arc::new(cluster))),
+            orcas: Arc::new(orca::SyncedOrcasPod::new(Arc::new(orca::OrcasPod::new()))),
+            apps: Arc::new(orca::SyncedApps::new(Arc::new(orca::Apps::new()))),
+            breakers: Arc::new(SyncedBreakers::new(Arc::new(Breakers::new()))),
+            changes: Arc::new(SyncedChangelog::new(Changelog::new(changes::DEFAULT_CAPACITY))),
+        }
+    }
+}
 
 type AuthHeaders = Vec<RawHeader>;
 type UuidNodeInfo = (String, Option<NodeInfo>);
+// (uuid, node info, unicorn node version)
+pub type FetchedNodeInfo = (String, Option<NodeInfo>, i64);
+pub type FetchFuture = Box<Future<Item=Vec<Option<FetchedNodeInfo>>, Error=CombinedError>>;
 
 
 #[derive(Debug, Clone)]
 pub struct NetInfo {
     hostname: String,
-    endpoints: Vec<Endpoint>
+    endpoints: Vec<Endpoint>,
+    orca_port: Option<u16>,
 }
 
+// (uuid, hostname, kind, unicorn node version)
+type NodeChange = (String, String, ChangeKind, Option<i64>);
+
 // TODO: generic collection
 trait ClusterInterface {
-    fn update(&mut self, nodes: &[UuidNodeInfo]);
+    // Applies fetched nodes, nodes without info are removed.
+    fn apply(&mut self, nodes: &[UuidNodeInfo]) -> Vec<NodeChange>;
     fn hosts(&self) -> HashMap<String, NetInfo>;
-    fn remove_not_in(&mut self, nodes: &[UuidNodeInfo]);
+    fn remove_not_in(&mut self, uuids: &[String]) -> Vec<NodeChange>;
 }
 
 impl ClusterInterface for Cluster {
-    fn remove_not_in(&mut self, nodes: &[UuidNodeInfo]) {
-        let fresh_uuids = nodes
+    fn remove_not_in(&mut self, uuids: &[String]) -> Vec<NodeChange> {
+        let fresh_uuids = uuids
             .iter()
-            .map(|&(ref uuid, _)| uuid.clone())
+            .cloned()
             .collect::<BTreeSet<_>>();
 
         let present_uuids = self
@@ -82,20 +135,43 @@ impl ClusterInterface for Cluster {
             .map(|k| k.clone())
             .collect::<BTreeSet<_>>();
 
+        let mut changes = Vec::new();
+
         for uuid in present_uuids.difference(&fresh_uuids) {
             println!("removing from cluster node {}", uuid);
-            self.remove(uuid);
+            if let Some(node) = self.remove(uuid) {
+                changes.push((uuid.clone(), node.hostname, ChangeKind::Left, None));
+            }
         }
+
+        changes
     }
 
-    fn update(&mut self, nodes: &[UuidNodeInfo]) {
-        self.remove_not_in(nodes);
+    fn apply(&mut self, nodes: &[UuidNodeInfo]) -> Vec<NodeChange> {
+        let mut changes = Vec::new();
 
         for &(ref uuid, ref info) in nodes {
-            if let &Some(ref info) = info {
-                self.entry(uuid.clone()).or_insert(info.clone());
+            match *info {
+                Some(ref info) => {
+                    let kind = match self.insert(uuid.clone(), info.clone()) {
+                        None => ChangeKind::Joined,
+                        Some(ref previous) if previous.unicorn_version != info.unicorn_version
+                            || previous.endpoints != info.endpoints
+                            || previous.orca_port != info.orca_port => ChangeKind::Changed,
+                        // Refetched as is, e.g. after subscription was restored.
+                        Some(_) => continue,
+                    };
+                    changes.push((uuid.clone(), info.hostname.clone(), kind, info.unicorn_version));
+                },
+                None => {
+                    if let Some(node) = self.remove(uuid) {
+                        changes.push((uuid.clone(), node.hostname, ChangeKind::Left, None));
+                    }
+                },
             }
         }
+
+        changes
     }
 
     fn hosts(&self) -> HashMap<String, NetInfo> {
@@ -103,7 +179,8 @@ impl ClusterInterface for Cluster {
         for (uuid, node_info) in self {
             let net = NetInfo {
                 hostname: node_info.hostname.clone(),
-                endpoints: node_info.endpoints.clone()
+                endpoints: node_info.endpoints.clone(),
+                orca_port: node_info.orca_port,
             };
             endpoints.entry(uuid.clone()).or_insert(net);
         }
@@ -111,25 +188,170 @@ impl ClusterInterface for Cluster {
     }
 }
 
+fn record_changes(
+    changelog: &SyncedChangelog,
+    cluster: &str,
+    snapshot_version: Option<i64>,
+    changes: Vec<NodeChange>,
+    now: u64)
+{
+    let mut changelog = changelog.write().unwrap();
+
+    for (uuid, hostname, kind, node_version) in changes {
+        changelog.push(Change {
+            timestamp: now,
+            cluster: cluster.to_string(),
+            uuid,
+            hostname,
+            kind,
+            node_version,
+            snapshot_version,
+        });
+    }
+}
+
+/// Drops all nodes of cluster, e.g. once they are stale for too long.
+pub fn clear_cluster(view: &ClusterView, name: &str, now: u64) {
+    let changes = Arc::make_mut(&mut view.cluster.write().unwrap()).remove_not_in(&[]);
+    record_changes(&view.changes, name, None, changes, now);
+}
+
+/// Replaces all nodes of cluster with given ones, for sources listing the
+/// whole cluster at once.
+pub fn replace_nodes(view: &ClusterView, name: &str, nodes: Vec<(String, NodeInfo)>, now: u64) {
+    let uuids: Vec<_> = nodes.iter().map(|&(ref uuid, _)| uuid.clone()).collect();
+    let nodes: Vec<UuidNodeInfo> = nodes.into_iter().map(|(uuid, info)| (uuid, Some(info))).collect();
+
+    let changes = {
+        let mut cluster = view.cluster.write().unwrap();
+        let cluster = Arc::make_mut(&mut cluster);
+        let mut changes = cluster.remove_not_in(&uuids);
+        changes.extend(cluster.apply(&nodes));
+        changes
+    };
+
+    record_changes(&view.changes, name, None, changes, now);
+}
+
+/// Marks nodes as kept from lost subscription, returns time the oldest of
+/// them is stale since, if any.
+pub fn mark_stale(cluster: &SyncedCluster, now: u64) -> Option<u64> {
+    let mut cluster = cluster.write().unwrap();
+    let cluster = Arc::make_mut(&mut cluster);
+
+    for node in cluster.values_mut() {
+        node.stale_since = node.stale_since.or(Some(now));
+    }
+
+    cluster.values().filter_map(|node| node.stale_since).min()
+}
+
+// Unicorn version and fetch time of node info.
+#[derive(Debug, Clone)]
+struct FetchedNode {
+    version: i64,
+    timestamp: u64,
+}
+
+// Bookkeeping of subscription stream, so only new, changed or outdated
+// nodes are fetched.
+#[derive(Debug)]
+struct NodesTracker {
+    last_version: Option<i64>,
+    // Node list of the last accepted message.
+    uuids: Vec<String>,
+    // mapping: uuid -> fetched node
+    fetched: HashMap<String, FetchedNode>,
+}
+
+impl NodesTracker {
+    fn new() -> NodesTracker {
+        NodesTracker {
+            last_version: None,
+            uuids: Vec::new(),
+            fetched: HashMap::new(),
+        }
+    }
+
+    // Returns `false` for stale or duplicated message.
+    fn accept(&mut self, version: i64, uuids: Vec<String>) -> bool {
+        if self.last_version.map_or(false, |last| version <= last) {
+            return false;
+        }
+
+        let fresh = uuids.iter().collect::<BTreeSet<_>>();
+        self.fetched.retain(|uuid, _| fresh.contains(uuid));
+
+        self.last_version = Some(version);
+        self.uuids = uuids;
+
+        true
+    }
+
+    // Nodes never fetched or fetched too long ago.
+    fn to_fetch(&self, now: u64) -> Vec<String> {
+        self.uuids.iter()
+            .filter(|uuid| match self.fetched.get(*uuid) {
+                Some(node) => now.saturating_sub(node.timestamp) >= NODE_REFRESH_SEC,
+                None => true
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Returns `true` if node is new or its version has changed.
+    fn record(&mut self, uuid: &str, version: i64, now: u64) -> bool {
+        let previous = self.fetched.insert(uuid.to_string(), FetchedNode { version, timestamp: now });
+        previous.map_or(true, |node| node.version != version)
+    }
+}
+
+// Subscription messages and periodic refresh ticks are processed in order.
+pub enum NodesEvent {
+    Update(SubscribeMessage),
+    Refresh,
+}
+
 fn make_auth_headers(header: Option<String>) -> Option<AuthHeaders>  {
     header.and_then(|hdr|
         Some(vec![ RawHeader::new("authorization".as_bytes(), hdr.into_bytes()) ])
     )
 }
 
-// TODO: subscribe to wide set of endpoints
-pub fn subscription<'a>(unicorn: &'a Unicorn, handle: Handle, config: &Config, path: &'a str, cluster: Arc<SyncedCluster>)
-    -> Box<Future<Item=(), Error=CombinedError> + 'a>
+/// Makes value of `Authorization` header for orca requests, if any configured.
+pub fn orca_auth_header(auth: &Option<OrcaAuth>, proxy: &mut SecureProxy)
+    -> Box<Future<Item=Option<String>, Error=CombinedError>>
 {
-    let proxy = make_ticket_service(Service::new("tvm", &handle), &config);
-    let proxy = Rc::new(RefCell::new(proxy));
+    match *auth {
+        Some(OrcaAuth::Ticket) => proxy.ticket_as_header(),
+        Some(OrcaAuth::Bearer(ref token)) =>
+            Box::new(future::ok(Some(format!("Bearer {}", token)))),
+        Some(OrcaAuth::Basic { ref user, ref password }) => {
+            let credentials = base64::encode(&format!("{}:{}", user, password));
+            Box::new(future::ok(Some(format!("Basic {}", credentials))))
+        },
+        None => Box::new(future::ok(None))
+    }
+}
 
+pub fn subscription(
+    unicorn: Service,
+    handle: Handle,
+    name: &str,
+    kids: &str,
+    locator: &Locator,
+    locator_addrs: Vec<net::SocketAddr>,
+    view: ClusterView,
+    proxy: SharedProxy,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher)
+    -> Box<Future<Item=(), Error=CombinedError>>
+{
     let (tx, rx) = mpsc::channel::<SubscribeMessage>(SUBSCRIBE_QUEUE_SIZE);
 
-    let subscribe_path = String::from(path);
+    let subscribe_path = kids.to_string();
 
     let subscibe_future = proxy.borrow_mut().ticket_as_header()
-        .map_err(CombinedError::CocaineError)
         .and_then(move |header| {
             println!("subscribing to path: {}", subscribe_path);
             kids_subscribe(
@@ -140,82 +362,246 @@ pub fn subscription<'a>(unicorn: &'a Unicorn, handle: Handle, config: &Config, p
             )
         });
 
-    let path = String::from(path);
-
-    let spawn_handle = handle.clone();
+    let path = kids.to_string();
+    let name = name.to_string();
+    let unicorn_service = locator.unicorn_service.clone();
     let node_handler = handle.clone();
 
-    let nodes_future = rx.for_each(move |(version, nodes)| {
-        println!("got from queue {} item(s) with version {}", nodes.len(), version);
+    let refresh = match Interval::new(time::Duration::from_secs(NODE_REFRESH_CHECK_SEC), &handle) {
+        Ok(interval) => interval,
+        Err(e) => return Box::new(future::err(CombinedError::IOError(e)))
+    };
 
-        let proxy = Rc::clone(&proxy);
-        let cluster = Arc::clone(&cluster);
-        let node_handler = node_handler.clone();
+    let events = rx
+        .map(NodesEvent::Update)
+        .map_err(|_| CombinedError::Other("subscription queue has failed".to_string()))
+        .select(refresh.map(|_| NodesEvent::Refresh).map_err(CombinedError::IOError));
+
+    let fetch = move |to_fetch: Vec<String>| -> FetchFuture {
+        let handle = node_handler.clone();
+        let unicorn_service = unicorn_service.clone();
+        let addrs = locator_addrs.clone();
         let path = path.clone();
 
-        let processing_future = proxy.borrow_mut().ticket_as_header()
-            .map_err(CombinedError::CocaineError)
+        let fetched = proxy.borrow_mut().ticket_as_header()
             .and_then(move |header| {
-                let mut results = Vec::with_capacity(nodes.len());
-
-                let handle = node_handler.clone();
+                let mut results = Vec::with_capacity(to_fetch.len());
 
-                for uuid in nodes {
+                for uuid in to_fetch {
                     let auth_hdr = make_auth_headers(header.clone());
-                    let node_path = format!("{}/{}", path.clone(), uuid);
-
-                    let ft = get_node::<_, NodeInfo>(Service::new("unicorn", &handle), auth_hdr, &node_path)
-                        .and_then(move |data| Ok((uuid, data)));
+                    let node_path = format!("{}/{}", path, uuid);
+
+                    let unicorn = make_resolved_service(&unicorn_service, &handle, &addrs)?;
+                    let ft = get_node::<_, NodeInfo>(unicorn, auth_hdr, &node_path)
+                        .then(move |result| match result {
+                            Ok((data, version)) => Ok::<_, CombinedError>(Some((uuid, data, version))),
+                            // Node isn't recorded as fetched, so it is retried on next event.
+                            Err(e) => {
+                                println!("failed to fetch node {} with error {:?}", uuid, e);
+                                Ok(None)
+                            }
+                        });
 
                     results.push(ft);
                 }
 
-                future::join_all(results)
+                Ok(future::join_all(results))
             })
-            .and_then(move |nodes| {
-                let mut cls = cluster.write().unwrap();
-                cls.update(&nodes);
+            .flatten();
+
+        Box::new(fetched)
+    };
+
+    let nodes_future = process_nodes(events, fetch, name, view, health, publisher)
+        .map_err(|e| {
+            println!("cluster nodes processing has stopped with error {:?}", e);
+            e
+        });
+
+    // Nodes are processed as long as subscription lasts.
+    let work = subscibe_future
+        .select(nodes_future)
+        .map(|_| ())
+        .map_err(|(e, _)| e);
+
+    Box::new(work)
+}
+
+/// Applies subscription events to cluster view, nodes listed in update are
+/// obtained with `fetch`, which resolves to `None` for node failed to fetch.
+pub fn process_nodes<S, F>(
+    events: S,
+    fetch: F,
+    name: String,
+    view: ClusterView,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher)
+    -> Box<Future<Item=(), Error=CombinedError>>
+where
+    S: Stream<Item=NodesEvent, Error=CombinedError> + 'static,
+    F: Fn(Vec<String>) -> FetchFuture + 'static,
+{
+    let tracker = Rc::new(RefCell::new(NodesTracker::new()));
+
+    // Events are processed one by one, so nodes are never updated out of order.
+    let nodes_future = events.for_each(move |event| {
+        let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        // Changes found by periodic refresh aren't tied to subscription version.
+        let (to_fetch, snapshot_version) = {
+            let mut tracker = tracker.borrow_mut();
+
+            let snapshot_version = match event {
+                NodesEvent::Update((version, nodes)) => {
+                    println!("got from queue {} item(s) with version {} for cluster {}", nodes.len(), version, name);
+
+                    if !tracker.accept(version, nodes) {
+                        println!("skipping outdated version {} for cluster {}", version, name);
+                        return Box::new(future::ok(())) as Box<Future<Item=(), Error=CombinedError>>;
+                    }
+
+                    health.write().unwrap().subscription_seen(&name, version);
+
+                    let left = Arc::make_mut(&mut view.cluster.write().unwrap()).remove_not_in(&tracker.uuids);
+                    if !left.is_empty() {
+                        record_changes(&view.changes, &name, Some(version), left, now);
+                        publisher.publish_nodes(&name);
+                    }
+
+                    Some(version)
+                },
+                NodesEvent::Refresh => None,
+            };
+
+            (tracker.to_fetch(now), snapshot_version)
+        };
+
+        let subsystem = Subsystem::Subscription(name.clone());
+        if to_fetch.is_empty() {
+            health.write().unwrap().success(&subsystem);
+            return Box::new(future::ok(()));
+        }
+
+        println!("fetching {} node(s) for cluster {}", to_fetch.len(), name);
+
+        let tracker = Rc::clone(&tracker);
+        let view = view.clone();
+        let health = Arc::clone(&health);
+        let name = name.clone();
+        let name_on_error = name.clone();
+        let publisher = publisher.clone();
+
+        let processing_future = fetch(to_fetch)
+            .and_then(move |nodes: Vec<Option<FetchedNodeInfo>>| {
+                let mut tracker = tracker.borrow_mut();
+
+                // Nodes failed to fetch are retried with the next event, but
+                // batch without a single node fetched means unicorn is unusable.
+                let requested = nodes.len();
+                let fetched: Vec<FetchedNodeInfo> = nodes.into_iter()
+                    .filter_map(|node| node)
+                    .collect();
+
+                if fetched.is_empty() {
+                    let error = format!("failed to fetch all of {} requested node(s)", requested);
+                    return Err(CombinedError::Other(error));
+                }
+
+                let changed: Vec<UuidNodeInfo> = fetched.into_iter()
+                    .filter_map(|(uuid, info, version)| match tracker.record(&uuid, version, now) {
+                        true => {
+                            let info = info.map(|info| NodeInfo {
+                                cluster: name.clone(),
+                                unicorn_version: Some(version),
+                                ..info
+                            });
+                            Some((uuid, info))
+                        },
+                        false => None
+                    })
+                    .collect();
+
+                println!("{} node(s) changed", changed.len());
+                let changes = Arc::make_mut(&mut view.cluster.write().unwrap()).apply(&changed);
+                if !changes.is_empty() {
+                    record_changes(&view.changes, &name, snapshot_version, changes, now);
+                    publisher.publish_nodes(&name);
+                }
                 Ok(())
             })
-            .then(|result| match result {
+            .then(move |result| match result {
                 // TODO: print timestamp
-                Ok(_) => { println!("state has been updated"); Ok(()) },
-                Err(err) => { println!("Error {:?}", err); Ok(()) }
+                Ok(_) => {
+                    println!("state has been updated");
+                    health.write().unwrap().success(&subsystem);
+                    Ok::<_, CombinedError>(())
+                },
+                Err(err) => {
+                    println!("failed to update state of cluster {}: {:?}", name_on_error, err);
+                    health.write().unwrap().failure(&subsystem, format!("{:?}", err));
+                    Ok(())
+                }
             });
 
-        spawn_handle.spawn(processing_future);
-        Ok(())
+        Box::new(processing_future)
     });
 
-    handle.spawn(nodes_future);
-    Box::new(subscibe_future)
+    Box::new(nodes_future)
 }
 
 
 type OrcaRequestResult = (String, orca::Orca); // (hostname, orca)
 
-fn make_requests_v1<'a, C>(
-    client: &'a hyper::client::Client<C>, endpoint: Endpoint, net_info: &NetInfo)
-    -> Box<Future<Item=OrcaRequestResult, Error=CombinedError> + 'a>
+fn make_requests_v1<C>(
+    client: &hyper::client::Client<C>,
+    endpoint: Option<Endpoint>,
+    net_info: &NetInfo,
+    web: &OrcaWeb,
+    auth: Option<String>)
+    -> Box<Future<Item=OrcaRequestResult, Error=CombinedError>>
 where
-    C: hyper::client::Connect + 'a
+    C: hyper::client::Connect
 {
-    fn ip6_uri_from_string(uri: &str, port: u16, path: &str)
+    fn ip_uri_from_string(scheme: &str, addr: &net::IpAddr, port: u16, path: &str)
+        -> Result<hyper::Uri, hyper::error::UriError>
+    {
+        let uri = match *addr {
+            net::IpAddr::V6(ref addr) => format!("{}://[{}]:{}/{}", scheme, addr, port, path),
+            net::IpAddr::V4(ref addr) => format!("{}://{}:{}/{}", scheme, addr, port, path),
+        };
+        uri.parse::<hyper::Uri>()
+    }
+
+    fn hostname_uri_from_string(scheme: &str, hostname: &str, port: u16, path: &str)
         -> Result<hyper::Uri, hyper::error::UriError>
     {
-        let uri = format!("{}://[{}]:{}/{}", orca::DEFAULT_WEB_SCHEME, uri, port, path);
+        let uri = format!("{}://{}:{}/{}", scheme, hostname, port, path);
         uri.parse::<hyper::Uri>()
     }
 
-    // TODO: make connector pluggable
-    fn get<'a,C,T>(client: &'a hyper::Client<C>, uri: hyper::Uri)
-        -> Box<Future<Item=T, Error=CombinedError> + 'a>
+    // Certificate can't be verified against bare ip address, so host is
+    // requested by name if verification is on, as well as host without
+    // known address, the name is resolved by client off the reactor.
+    let port = net_info.orca_port.unwrap_or(web.port);
+    let addr = endpoint.as_ref().and_then(|endpoint| net::IpAddr::from_str(&endpoint.host_str()).ok());
+    let make_uri = |path: &str| match addr {
+        Some(ref addr) if !web.use_hostname() => ip_uri_from_string(&web.scheme, addr, port, path),
+        _ => hostname_uri_from_string(&web.scheme, &net_info.hostname, port, path),
+    };
+
+    fn get<C,T>(client: &hyper::Client<C>, uri: hyper::Uri, auth: Option<String>)
+        -> Box<Future<Item=T, Error=CombinedError>>
     where
-        C: hyper::client::Connect + 'a,
-        T: serde::de::DeserializeOwned + 'a
+        C: hyper::client::Connect,
+        T: serde::de::DeserializeOwned + 'static
     {
         // println!("get for {:?}", uri);
-        let data = client.get(uri)
+        let mut request = hyper::Request::new(hyper::Method::Get, uri);
+        if let Some(auth) = auth {
+            request.headers_mut().set_raw("Authorization", auth);
+        }
+
+        let data = client.request(request)
             .and_then(|res| {
                 // println!("result {}", res.status());
                 res.body().fold(Vec::new(), |mut acc, chunk| {
@@ -238,59 +624,68 @@ where
         return format!("{}/{}", api_ver, math)
     }
 
-    let info_uri = ip6_uri_from_string(&endpoint.host_str(), orca::DEFAULT_WEB_PORT, "info");
+    let info_uri = make_uri("info");
 
     // api version could be taken from info handle, hardcoded for now
-    let state_uri = ip6_uri_from_string(&endpoint.host_str(), orca::DEFAULT_WEB_PORT, &make_path("v1", "state"));
-    let metrics_uri = ip6_uri_from_string(&endpoint.host_str(), orca::DEFAULT_WEB_PORT, &make_path("v1", "metrics?flatten"));
-    let dist_uri = ip6_uri_from_string(&endpoint.host_str(), orca::DEFAULT_WEB_PORT, &make_path("v1", "distribution"));
-    let incoming_uri = ip6_uri_from_string(&endpoint.host_str(), orca::DEFAULT_WEB_PORT, &make_path("v1", "incoming_state"));
+    let state_uri = make_uri(&make_path("v1", "state"));
+    let metrics_uri = make_uri(&make_path("v1", "metrics?flatten"));
+    let dist_uri = make_uri(&make_path("v1", "distribution"));
+    let incoming_uri = make_uri(&make_path("v1", "incoming_state"));
 
+    let info_auth = auth.clone();
+    let info_client = client.clone();
     let info_future = future::result(info_uri)
         .map_err(CombinedError::UriParseError)
         .and_then(|uri| { // TODO: Debug clusure, remove
             // println!("making info request for {:?}", uri);
             Ok(uri)
         })
-        .and_then(move |uri| get::<C, orca::Info>(client, uri));
+        .and_then(move |uri| get::<C, orca::Info>(&info_client, uri, info_auth));
 
+    let state_auth = auth.clone();
+    let state_client = client.clone();
     let state_future = future::result(state_uri)
         .map_err(CombinedError::UriParseError)
         .and_then(|uri| { // TODO: Debug clusure, remove
             // println!("making state request {:?}", uri);
             Ok(uri)
         })
-        .and_then(move |uri| get::<C, orca::CommittedState>(client, uri));
+        .and_then(move |uri| get::<C, orca::CommittedState>(&state_client, uri, state_auth));
 
+    let metrics_auth = auth.clone();
+    let metrics_client = client.clone();
     let metrics_future = future::result(metrics_uri)
         .map_err(CombinedError::UriParseError)
         .and_then(|uri| { // TODO: Debug clusure, remove
             // println!("making metrics request {:?}", uri);
             Ok(uri)
         })
-        .and_then(move |uri| get::<C, orca::Metrics>(client, uri))
+        .and_then(move |uri| get::<C, orca::Metrics>(&metrics_client, uri, metrics_auth))
         .or_else(|_| Ok(orca::Metrics::new()));
 
+    let dist_auth = auth.clone();
+    let dist_client = client.clone();
     let dist_future = future::result(dist_uri)
         .map_err(CombinedError::UriParseError)
         .and_then(|uri| { // TODO: Debug clusure, remove
             // println!("making distribution request {:?}", uri);
             Ok(uri)
         })
-        .and_then(move |uri| get::<C, orca::WorkersDistribution>(client, uri))
+        .and_then(move |uri| get::<C, orca::WorkersDistribution>(&dist_client, uri, dist_auth))
         .or_else(|_| Ok(orca::WorkersDistribution::new()));
 
+    let incoming_auth = auth;
+    let incoming_client = client.clone();
     let incoming_future = future::result(incoming_uri)
         .map_err(CombinedError::UriParseError)
         .and_then(|uri| { // TODO: Debug clusure, remove
             // println!("making distribution request {:?}", uri);
             Ok(uri)
         })
-        .and_then(move |uri| get::<C, orca::IncomingState>(client, uri))
+        .and_then(move |uri| get::<C, orca::IncomingState>(&incoming_client, uri, incoming_auth))
         .or_else(|_| Ok(orca::IncomingState::new()));
 
     let hostname = net_info.hostname.clone();
-    let endpoint = endpoint.clone();
 
     let request_result = info_future
         .join(state_future)
@@ -303,7 +698,7 @@ where
                         &incoming_state, &committed_state, &distribution);
                 let mismatched = orca::make_mismatched_list(&distribution);
                 let orca = orca::Orca {
-                    endpoints: vec![ endpoint ],
+                    endpoints: endpoint.into_iter().collect(),
                     committed_state,
                     metrics,
                     info,
@@ -328,25 +723,50 @@ fn dump_cls(cls: &Cluster) {
 }
 
 
-pub fn gather<'a,C>(client: &'a hyper::client::Client<C>, cluster: Arc<SyncedCluster>, orcas: Arc<orca::SyncedOrcasPod>)
-    -> Box<Future<Item=(), Error=CombinedError> + 'a>
+/// Requests orcas of cluster hosts, resolves to number of hosts gathered.
+pub fn gather<C>(
+    client: &hyper::client::Client<C>,
+    cluster: Arc<SyncedCluster>,
+    orcas: Arc<orca::SyncedOrcasPod>,
+    breakers: Arc<SyncedBreakers>,
+    breaker_config: BreakerConfig,
+    web: OrcaWeb,
+    auth: Option<String>)
+    -> Box<Future<Item=usize, Error=CombinedError>>
 where
-    C: hyper::client::Connect + 'a
+    C: hyper::client::Connect
 {
 
-    fn is_ipv6(addr: &Endpoint) -> bool {
-        match net::IpAddr::from_str(&addr.host_str()) {
-            Ok(addr) => addr.is_ipv6(),
-            _ => false
-        }
+    // Ipv6 address is preferred, but any will do.
+    //
+    // TODO: first address taken (if any), but should we peek a random one?
+    fn pick_endpoint(endpoints: &[Endpoint]) -> Option<Endpoint> {
+        let addrs: Vec<_> = endpoints.iter()
+            .filter_map(|ep| net::IpAddr::from_str(&ep.host_str()).ok().map(|addr| (ep, addr)))
+            .collect();
+
+        addrs.iter().find(|&&(_, addr)| addr.is_ipv6())
+            .or_else(|| addrs.first())
+            .map(|&(ep, _)| ep.clone())
     }
 
     let hosts = cluster.read().unwrap().hosts();
     println!("cluster size is {}", hosts.len());
 
-    let mut gather_strides = Vec::with_capacity(cluster.read().unwrap().len());
+    let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
 
-    for (num, (uuid, net)) in hosts.into_iter().enumerate() {
+    { // Forget breakers of hosts gone from cluster.
+        let hostnames: Vec<_> = hosts.values().map(|net| net.hostname.clone()).collect();
+        Arc::make_mut(&mut breakers.write().unwrap()).retain_hosts(&hostnames);
+    }
+
+    let mut gather_strides = Vec::with_capacity(hosts.len());
+
+    for (num, (_, net)) in hosts.into_iter().enumerate() {
+
+        if !Arc::make_mut(&mut breakers.write().unwrap()).should_probe(&net.hostname, now, &breaker_config) {
+            continue;
+        }
 
         let to_pause = num as u64 % GATHER_INTERVAL_SECS;
         let to_sleep = time::Duration::new(to_pause, 0);
@@ -356,60 +776,61 @@ where
             return Box::new(future::err(CombinedError::IOError(e)));
         }
 
+        let hostname = net.hostname.clone();
+        let client = client.clone();
+        let web = web.clone();
+        let auth = auth.clone();
+
         let gather_bootstrap = gather_bootstrap.unwrap()
             .map_err(CombinedError::IOError)
             .and_then(move |_| {
-                let eps_v6: Vec<_> = net.endpoints.iter().filter(|net| is_ipv6(net)).collect();
-
                 // println!("making request for uuid {:?} {:?}", to_sleep, uuid);
 
-                //
-                // TODO: first address taken (if any), but should we peek a random one?
-                //
-                match eps_v6.first() {
-                    Some(ref ep) => make_requests_v1(client, (**ep).clone(), &net),
-                    None => {
-                        let error_message = format!("can't find ip6 address for uuid {} within host {:?}", uuid, net);
-                        Box::new(future::err(CombinedError::Other(error_message)))
-                    }
-                }
+                // Host without address is requested by name.
+                make_requests_v1(&client, pick_endpoint(&net.endpoints), &net, &web, auth)
             })
-            .then(|r| match r {
-                Ok(r) => Ok(Some(r)),
-                //
-                // TODO: For now error is ignored silently, but we should
-                //       report it to some kind of logger someday.
-                Err(_e) => {
-                    // println!("error on request {:?}", e);
-                    Ok(None)
-                }
-            });
+            //
+            // TODO: For now error is only recorded in host breaker, but we
+            //       should report it to some kind of logger someday.
+            .then(move |r| Ok::<_, CombinedError>((hostname, r)));
 
         gather_strides.push(gather_bootstrap);
-    } // for (num, (uuid, net))
+    } // for (num, (_, net))
 
     let result = future::join_all(gather_strides)
         .and_then(move |responses| {
 
             let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
             let span = time::Duration::from_secs(SPOILED_ORCA_EXPIRATION_SEC);
-            { // Remove old records.
+            let mut gathered = 0;
+
+            {
                 let mut orcas = orcas.write().unwrap();
+                let mut breakers = breakers.write().unwrap();
+                let (orcas, breakers) = (Arc::make_mut(&mut orcas), Arc::make_mut(&mut breakers));
+
+                // Remove old records.
                 orcas.retain(|_host, orca|
                     now - time::Duration::from_secs(orca.update_timestamp) < span);
-            }
 
-            {
-                let mut orcas = orcas.write().unwrap();
-                for val in responses {
-                    if let Some((host, orca)) = val {
-                        let record = orca::OrcaRecord { orca, update_timestamp: now.as_secs() };
-                        orcas.insert(host, record);
+                for (hostname, val) in responses {
+                    match val {
+                        Ok((host, orca)) => {
+                            breakers.on_success(&hostname);
+
+                            let record = orca::OrcaRecord { orca, update_timestamp: now.as_secs() };
+                            orcas.insert(host, record);
+                            gathered += 1;
+                        },
+                        Err(e) => {
+                            let error = format!("{:?}", e);
+                            breakers.on_failure(&hostname, now.as_secs(), error, &breaker_config);
+                        }
                     }
                 }
             }
 
-            Ok(())
+            Ok(gathered)
         });
 
     Box::new(result)
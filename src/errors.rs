@@ -2,6 +2,8 @@ use cocaine;
 use std;
 use hyper;
 use serde_json;
+use native_tls;
+use openssl;
 
 use futures::sync::mpsc::SendError;
 
@@ -15,6 +17,11 @@ pub enum CombinedError {
     IOError(std::io::Error),
     HyperError(hyper::Error),
     SerdeError(serde_json::Error),
+    TlsError(native_tls::Error),
+    OpenSslError(openssl::error::ErrorStack),
+    ConfigError(String),
+    // None of locator endpoints is reachable.
+    LocatorError(String),
     Other(String),
 }
 
@@ -41,3 +48,15 @@ impl From<hyper::Error> for CombinedError {
         CombinedError::HyperError(err)
     }
 }
+
+impl From<native_tls::Error> for CombinedError {
+    fn from(err: native_tls::Error) -> Self {
+        CombinedError::TlsError(err)
+    }
+}
+
+impl From<openssl::error::ErrorStack> for CombinedError {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        CombinedError::OpenSslError(err)
+    }
+}
@@ -0,0 +1,240 @@
+//
+// Liveness and readiness of zorca subsystems.
+//
+use std::cmp;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{self, Duration, UNIX_EPOCH};
+
+use secure::TicketInfo;
+
+
+// Subsystem is considered stuck if it hasn't made any progress for that long,
+// heartbeat is recorded on each subscription event, including periodic node
+// refresh check, and on each gather cycle completion, so it should be well
+// above both periods.
+pub const STUCK_THRESHOLD_SEC: u64 = 5 * 60;
+
+
+#[derive(Debug, Clone)]
+pub enum Subsystem {
+    // Discovery of nodes of named cluster, e.g. unicorn subscription.
+    Subscription(String),
+    Gather,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    pub heartbeat: u64,
+    pub last_success: u64,
+    // Subscription is established or last gather cycle succeeded.
+    pub active: bool,
+    // Nothing to wait for, e.g. static hosts, so no progress is expected.
+    pub idle: bool,
+    // No progress is expected till then, e.g. while waiting to resubscribe.
+    pub backoff_until: u64,
+    pub last_error: Option<String>,
+}
+
+impl SubsystemHealth {
+    fn new(now: u64) -> SubsystemHealth {
+        SubsystemHealth {
+            heartbeat: now,
+            last_success: 0,
+            active: false,
+            idle: false,
+            backoff_until: 0,
+            last_error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthState {
+    // mapping: cluster name -> subscription health
+    pub subscriptions: BTreeMap<String, SubsystemHealth>,
+    pub gather: SubsystemHealth,
+    // Whether credentials should be obtained from secure service.
+    pub ticket_required: bool,
+
+    // Version of the last subscription message seen, mapping: cluster name -> version
+    pub subscription_versions: BTreeMap<String, i64>,
+    pub gather_cycles: u64,
+    pub gather_duration_ms: u64,
+}
+
+pub type SyncedHealth = RwLock<HealthState>;
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub last_success: u64,
+    pub heartbeat: u64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub ok: bool,
+    pub checks: BTreeMap<String, Check>,
+}
+
+pub fn now_secs() -> u64 {
+    time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl HealthState {
+    pub fn new(ticket_required: bool, clusters: &[String]) -> HealthState {
+        let now = now_secs();
+
+        HealthState {
+            subscriptions: clusters.iter()
+                .map(|name| (name.clone(), SubsystemHealth::new(now)))
+                .collect(),
+            gather: SubsystemHealth::new(now),
+            ticket_required,
+            subscription_versions: BTreeMap::new(),
+            gather_cycles: 0,
+            gather_duration_ms: 0,
+        }
+    }
+
+    fn get_mut(&mut self, subsystem: &Subsystem) -> &mut SubsystemHealth {
+        match *subsystem {
+            Subsystem::Subscription(ref name) => self.subscriptions
+                .entry(name.clone())
+                .or_insert_with(|| SubsystemHealth::new(now_secs())),
+            Subsystem::Gather => &mut self.gather,
+        }
+    }
+
+    pub fn beat(&mut self, subsystem: &Subsystem) {
+        self.get_mut(subsystem).heartbeat = now_secs();
+    }
+
+    pub fn success(&mut self, subsystem: &Subsystem) {
+        let now = now_secs();
+        let state = self.get_mut(subsystem);
+
+        state.heartbeat = now;
+        state.last_success = now;
+        state.active = true;
+    }
+
+    /// Marks subsystem as succeeded once and for all.
+    pub fn idle(&mut self, subsystem: &Subsystem) {
+        self.success(subsystem);
+        self.get_mut(subsystem).idle = true;
+    }
+
+    /// Marks subsystem as waiting for the next attempt for `delay`.
+    pub fn backoff(&mut self, subsystem: &Subsystem, delay: Duration) {
+        let state = self.get_mut(subsystem);
+
+        state.heartbeat = now_secs();
+        state.backoff_until = state.heartbeat + delay.as_secs();
+    }
+
+    pub fn failure(&mut self, subsystem: &Subsystem, error: String) {
+        let state = self.get_mut(subsystem);
+
+        state.heartbeat = now_secs();
+        state.active = false;
+        state.last_error = Some(error);
+    }
+
+    pub fn subscription_seen(&mut self, cluster: &str, version: i64) {
+        self.subscription_versions.insert(cluster.to_string(), version);
+    }
+
+    pub fn gather_done(&mut self, duration: Duration) {
+        self.gather_cycles += 1;
+        self.gather_duration_ms = duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64;
+    }
+
+    /// Process is alive if subsystems are making progress.
+    pub fn liveness(&self) -> Report {
+        let now = now_secs();
+
+        let alive = |state: &SubsystemHealth| {
+            // Stuck threshold is counted from the end of backoff, if any.
+            let last_seen = cmp::max(state.heartbeat, state.backoff_until);
+            let stuck = !state.idle && now.saturating_sub(last_seen) > STUCK_THRESHOLD_SEC;
+            Check {
+                ok: !stuck,
+                last_success: state.last_success,
+                heartbeat: state.heartbeat,
+                reason: if stuck { Some(format!("no heartbeat for {} sec", now - state.heartbeat)) } else { None },
+            }
+        };
+
+        let mut checks: Vec<_> = self.subscriptions.iter()
+            .map(|(name, state)| (subscription_check_name(name), alive(state)))
+            .collect();
+        checks.push(("gather".to_string(), alive(&self.gather)));
+
+        make_report(checks)
+    }
+
+    /// Zorca is ready if subscription is established, orcas were gathered at
+    /// least once and secure ticket (if any) was obtained.
+    pub fn readiness(&self, ticket: &TicketInfo, stopping: bool) -> Report {
+        let subscription = |state: &SubsystemHealth| Check {
+            ok: state.active,
+            last_success: state.last_success,
+            heartbeat: state.heartbeat,
+            reason: if state.active { None } else {
+                Some(state.last_error.clone().unwrap_or("subscription is not established".to_string()))
+            },
+        };
+
+        let gathered = self.gather.last_success > 0;
+        let gather = Check {
+            ok: gathered,
+            last_success: self.gather.last_success,
+            heartbeat: self.gather.heartbeat,
+            reason: if gathered { None } else { Some("no successful gather yet".to_string()) },
+        };
+
+        // Failed background refresh doesn't matter till cached ticket expires,
+        // ticket without expiration is fetched on each use, so the last
+        // attempt decides.
+        let ticket_valid = !self.ticket_required || (ticket.last_updated > 0 && match ticket.expires_at {
+            0 => ticket.last_updated >= ticket.last_error_time,
+            expires_at => (now_secs() as i64) < expires_at,
+        });
+        let ticket = Check {
+            ok: ticket_valid,
+            last_success: ticket.last_updated as u64,
+            heartbeat: 0,
+            reason: if ticket_valid { None } else {
+                Some(ticket.last_error.clone().unwrap_or("ticket is not obtained yet".to_string()))
+            },
+        };
+
+        let lifecycle = Check {
+            ok: !stopping,
+            last_success: 0,
+            heartbeat: 0,
+            reason: if stopping { Some("shutting down".to_string()) } else { None },
+        };
+
+        let mut checks: Vec<_> = self.subscriptions.iter()
+            .map(|(name, state)| (subscription_check_name(name), subscription(state)))
+            .collect();
+        checks.push(("gather".to_string(), gather));
+        checks.push(("ticket".to_string(), ticket));
+        checks.push(("lifecycle".to_string(), lifecycle));
+
+        make_report(checks)
+    }
+}
+
+fn subscription_check_name(cluster: &str) -> String {
+    format!("subscription:{}", cluster)
+}
+
+fn make_report(checks: Vec<(String, Check)>) -> Report {
+    let ok = checks.iter().all(|&(_, ref check)| check.ok);
+    Report { ok, checks: checks.into_iter().collect() }
+}
@@ -0,0 +1,97 @@
+//
+// Zorca library: cluster discovery, orcas gathering and aggregation, and
+// web api, `main.rs` is command line wiring on top of it.
+//
+// Data model and config are public modules, the rest is re-exported below
+// explicitly, everything else is internal and may change without notice.
+//
+extern crate cocaine;
+extern crate futures;
+extern crate bytes;
+extern crate tokio_core;
+
+#[macro_use] extern crate serde_derive;
+extern crate serde;
+#[macro_use] extern crate serde_json;
+
+extern crate yaml_rust;
+
+extern crate time;
+
+extern crate hyper;
+extern crate hyper_staticfile;
+extern crate hyper_tls;
+extern crate base64;
+extern crate sha1;
+extern crate flate2;
+extern crate native_tls;
+extern crate openssl;
+extern crate tokio_openssl;
+extern crate tokio_signal;
+extern crate tokio_process;
+extern crate tokio_io;
+extern crate tokio_uds;
+
+#[cfg(test)] extern crate rmpv;
+
+mod samples;
+mod access;
+mod acceptor;
+mod backoff;
+mod cache;
+mod connector;
+mod secure;
+mod unicorn;
+mod locator;
+mod discovery;
+mod engine;
+mod web;
+mod server;
+mod lifecycle;
+mod health;
+mod simulator;
+mod snapshot;
+mod runner;
+
+// Data model.
+pub mod resources;
+pub mod orca;
+pub mod changes;
+pub mod breaker;
+pub mod config;
+pub mod errors;
+
+pub use backoff::BackoffConfig;
+pub use health::{HealthState, SyncedHealth};
+pub use secure::{SyncedTicketInfo, TicketInfo};
+pub use snapshot::{ClusterSnapshot, Snapshot};
+
+// Cluster nodes and orcas fetchers.
+pub use discovery::{Discovery, DiscoveryFuture, make_discovery};
+pub use engine::{
+    Cluster,
+    ClusterView,
+    ClusterViews,
+    FetchFuture,
+    FetchedNodeInfo,
+    NetInfo,
+    NodesEvent,
+    SubscribeMessage,
+    SyncedCluster,
+    gather,
+    process_nodes,
+};
+pub use locator::{probe, resolve, resolve_async};
+pub use connector::{OrcaClient, make_client};
+
+// Aggregation of gathered state.
+pub use engine::{clear_cluster, mark_stale, replace_nodes};
+pub use snapshot::Publisher;
+
+// Service and web api.
+pub use lifecycle::{EXIT_FAILURE, EXIT_OK};
+pub use runner::{Runner, RunnerBuilder};
+pub use simulator::{DEFAULT_LISTEN_HOST as DEFAULT_SIMULATOR_HOST, Scenario, SimulatorConfig, run as simulate};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+//
+// Process lifecycle: shutdown coordination between subscription, gather
+// and web loops.
+//
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use futures::future::{self, Shared};
+use futures::sync::oneshot;
+
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+use errors::CombinedError;
+
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+
+
+pub struct Lifecycle {
+    stopping: AtomicBool,
+    // Taken on the first stop request, dropping it wakes every waiter.
+    stop: Mutex<Option<oneshot::Sender<()>>>,
+    stopped: Shared<oneshot::Receiver<()>>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Lifecycle {
+        let (stop, stopped) = oneshot::channel();
+
+        Lifecycle {
+            stopping: AtomicBool::new(false),
+            stop: Mutex::new(Some(stop)),
+            stopped: stopped.shared(),
+        }
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// Future resolved once shutdown is requested, could be selected with
+/// long running work to cancel it.
+pub fn wait_stop(lifecycle: &Lifecycle) -> Box<Future<Item=(), Error=CombinedError>> {
+    Box::new(lifecycle.stopped.clone().then(|_| Ok::<_, CombinedError>(())))
+}
+
+/// Future resolved after duration or once shutdown is requested, whichever
+/// comes first.
+pub fn pause(handle: &Handle, duration: Duration, lifecycle: &Lifecycle) -> Box<Future<Item=(), Error=CombinedError>> {
+    let timeout = match Timeout::new(duration, handle) {
+        Ok(timeout) => timeout,
+        Err(e) => return Box::new(future::err(CombinedError::IOError(e)))
+    };
+
+    let future = timeout
+        .map_err(CombinedError::IOError)
+        .select(wait_stop(lifecycle))
+        .map(|_| ())
+        .map_err(|(e, _)| e);
+
+    Box::new(future)
+}
+
+/// Future resolved with signal number on first SIGTERM or SIGINT.
+pub fn stop_signals(handle: &Handle) -> Box<Future<Item=i32, Error=CombinedError>> {
+    let term = Signal::new(SIGTERM, handle).flatten_stream();
+    let int = Signal::new(SIGINT, handle).flatten_stream();
+
+    let future = term.select(int)
+        .into_future()
+        .map_err(|(e, _)| CombinedError::IOError(e))
+        .and_then(|(signal, _)| match signal {
+            Some(signal) => Ok(signal),
+            None => Err(CombinedError::Other("signal stream closed".to_string()))
+        });
+
+    Box::new(future)
+}
@@ -0,0 +1,118 @@
+//
+// Cocaine locator endpoints resolution and reachability check.
+//
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use futures::Future;
+use futures::future;
+use futures::sync::oneshot;
+
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Handle, Timeout};
+
+use cocaine::{Service, ServiceBuilder};
+
+use config::Locator;
+use errors::CombinedError;
+
+
+const CONNECT_TIMEOUT_SEC: u64 = 5;
+
+
+/// Resolves locator endpoints preserving their order, endpoints which
+/// can't be resolved are skipped.
+pub fn resolve(locator: &Locator) -> Vec<SocketAddr> {
+    let mut addrs = Vec::with_capacity(locator.endpoints.len());
+
+    for endpoint in &locator.endpoints {
+        match endpoint.to_socket_addrs() {
+            Ok(resolved) => addrs.extend(resolved),
+            Err(e) => println!("failed to resolve locator {}: {}", endpoint, e),
+        }
+    }
+
+    addrs
+}
+
+/// The same as `resolve`, but lookups are made on separate thread, so they
+/// don't stall the reactor. Fails if none of endpoints could be resolved.
+pub fn resolve_async(locator: &Locator) -> Box<Future<Item=Vec<SocketAddr>, Error=CombinedError>> {
+    let (tx, rx) = oneshot::channel();
+    let endpoints = locator.endpoints.clone();
+    let locator = locator.clone();
+
+    thread::spawn(move || {
+        // Receiver could be gone on shutdown, nobody to report to then.
+        let _ = tx.send(resolve(&locator));
+    });
+
+    let future = rx
+        .map_err(|_| CombinedError::LocatorError("locator resolution thread has failed".to_string()))
+        .and_then(move |addrs| match addrs.is_empty() {
+            true => Err(CombinedError::LocatorError(
+                format!("none of locator endpoints {:?} could be resolved", endpoints))),
+            false => Ok(addrs)
+        });
+
+    Box::new(future)
+}
+
+/// Makes service with already resolved locator addresses, to not resolve
+/// them on each of many requests.
+pub fn make_resolved_service(name: &str, handle: &Handle, addrs: &[SocketAddr]) -> Result<Service, CombinedError> {
+    // Cocaine default locator isn't what was configured, so no fallback.
+    if addrs.is_empty() {
+        return Err(CombinedError::LocatorError(format!("no locator addresses to resolve {} with", name)));
+    }
+
+    let service = ServiceBuilder::new(name.to_string())
+        .locator_addrs(addrs.to_vec())
+        .build(handle);
+
+    Ok(service)
+}
+
+/// Resolved once any of locator endpoints accepts connection, so locator
+/// failures could be told apart from ones of services behind it.
+pub fn probe(handle: &Handle, locator: &Locator) -> Box<Future<Item=SocketAddr, Error=CombinedError>> {
+    let handle = handle.clone();
+    let locator = locator.clone();
+
+    let future = resolve_async(&locator)
+        .and_then(move |addrs| probe_resolved(&handle, &locator, addrs));
+
+    Box::new(future)
+}
+
+/// The same as `probe`, for already resolved locator addresses.
+pub fn probe_resolved(handle: &Handle, locator: &Locator, addrs: Vec<SocketAddr>)
+    -> Box<Future<Item=SocketAddr, Error=CombinedError>>
+{
+    let timeout = match Timeout::new(Duration::from_secs(CONNECT_TIMEOUT_SEC), handle) {
+        Ok(timeout) => timeout,
+        Err(e) => return Box::new(future::err(CombinedError::IOError(e)))
+    };
+
+    let connects = addrs.into_iter()
+        .map(|addr| TcpStream::connect(&addr, handle).map(move |_| addr));
+
+    let endpoints = locator.endpoints.clone();
+    let timeout_endpoints = endpoints.clone();
+
+    let reachable = future::select_ok(connects)
+        .map(|(addr, _)| addr)
+        .map_err(move |e| CombinedError::LocatorError(
+            format!("none of locator endpoints {:?} is reachable: {}", endpoints, e)));
+
+    let timeout = timeout
+        .then(move |_| Err::<SocketAddr, _>(CombinedError::LocatorError(
+            format!("connection to locator endpoints {:?} has timed out", timeout_endpoints))));
+
+    let future = reachable.select(timeout)
+        .map(|(addr, _)| addr)
+        .map_err(|(e, _)| e);
+
+    Box::new(future)
+}
@@ -1,80 +1,64 @@
-#![feature(conservative_impl_trait)]
-#![feature(underscore_lifetimes)]
-//
-// TODO: integrate clap and yaml
-//
-extern crate cocaine;
-extern crate futures;
-extern crate tokio_core;
-
-#[macro_use] extern crate serde_derive;
-extern crate serde;
-extern crate serde_json;
-
-extern crate yaml_rust;
+extern crate zorca;
 
 #[macro_use] extern crate clap;
-extern crate time;
-
-extern crate hyper;
-extern crate hyper_staticfile;
-
-use clap::{App, Arg, ArgMatches};
-use std::sync::Arc;
-use std::net::SocketAddr;
-
-use futures::Stream;
 
-use tokio_core::reactor::Core;
-use tokio_core::net::TcpListener;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::Write;
 
-use hyper::server::Http;
+use zorca::{EXIT_FAILURE, EXIT_OK, RunnerBuilder, Scenario, SimulatorConfig};
+use zorca::config::{ClusterSource, Config, WebTls};
 
-use cocaine::Service;
-use cocaine::service::Unicorn;
 
-mod samples;
-mod config;
-mod secure;
-mod errors;
-mod unicorn;
-mod engine;
-mod orca;
-mod resources;
-mod web;
-
-use config::Config;
-use engine::{
-    Cluster,
-    SyncedCluster,
-    subscription,
-    gather,
-};
-
-use orca::{
-    SyncedApps,
-    SyncedOrcasPod,
-    OrcasPod,
-    AppsTrait,
-};
-
-use web::{WebApi, SelfInfo};
-
-use samples::make_dummy_cluster;
+// Command line kids paths take precedence over config clusters, each in form
+// of `name=path` or just `path`, which is used as name too.
+fn get_cluster_sources(options: &ArgMatches) -> Option<Vec<ClusterSource>> {
+    options.values_of("kids_path").map(|paths| paths
+        .map(|path| {
+            let (name, kids) = match path.find('=') {
+                Some(pos) => (&path[..pos], &path[pos + 1..]),
+                None => (path, path)
+            };
+            ClusterSource::unicorn(name, kids)
+        })
+        .collect())
+}
 
+fn get_values(options: &ArgMatches, name: &str) -> Option<Vec<String>> {
+    options.values_of(name).map(|values| values.map(String::from).collect())
+}
 
-const SUSPEND_DURATION_SEC: u64 = 10;
-const POLL_DURATION_SEC: u64 = 10;
 
+// Runs fake orca fleet instead of monitoring, returns exit code.
+fn simulate(options: &ArgMatches) -> i32 {
+    let scenario = match options.value_of("scenario") {
+        Some(path) => match Scenario::from_file(path) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                println!("failed to load scenario: {:?}", e);
+                return EXIT_FAILURE;
+            }
+        },
+        None => Scenario::new()
+    };
 
-struct Context<'a> {
-    config: Config,
-    options: ArgMatches<'a>
-}
+    let config = SimulatorConfig {
+        hosts: value_t!(options, "hosts", usize).unwrap_or_else(|e| e.exit()),
+        listen_host: options.value_of("listen_host").unwrap_or(zorca::DEFAULT_SIMULATOR_HOST).to_string(),
+        base_port: value_t!(options, "base_port", u16).unwrap_or_else(|e| e.exit()),
+        hosts_file: options.value_of("hosts_file").map(String::from),
+        scenario,
+        tls: match (options.value_of("tls_cert"), options.value_of("tls_key")) {
+            (Some(cert), Some(key)) => Some(WebTls { cert: cert.to_string(), key: key.to_string(), client_ca: None }),
+            _ => None
+        },
+    };
 
-impl<'a> Context<'a> {
-    fn get_listen_path(&self) -> &str {
-        self.options.value_of("kids_path").unwrap()
+    match zorca::simulate(config) {
+        Ok(_) => EXIT_OK,
+        Err(e) => {
+            println!("simulator has failed with error {:?}", e);
+            EXIT_FAILURE
+        }
     }
 }
 
@@ -85,131 +69,97 @@ fn main() {
         .arg(Arg::with_name("kids_path")
             .short("k")
             .long("kids")
-            .required(true)
             .takes_value(true)
-            .help("node to subscribe for kids updates"))
-        .arg(Arg::with_name("dummy_data")
-            .short("d")
-            .long("dummy")
-            .help("use dummy host data for testing and debuging"))
+            .multiple(true)
+            .number_of_values(1)
+            .help("node to subscribe for kids updates (path or name=path), could be repeated"))
+        .arg(Arg::with_name("listen")
+            .short("l")
+            .long("listen")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("web api address (ip:port, [ip6]:port or unix:/path), could be repeated"))
+        .arg(Arg::with_name("locator")
+            .long("locator")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("cocaine locator address (host:port), could be repeated, tried in order"))
+        .subcommand(SubCommand::with_name("simulate")
+            .about("serves fake orca fleet on local ports for demos and testing")
+            .arg(Arg::with_name("hosts")
+                .short("n")
+                .long("hosts")
+                .takes_value(true)
+                .default_value("6")
+                .help("number of simulated orcas"))
+            .arg(Arg::with_name("listen_host")
+                .long("listen-host")
+                .takes_value(true)
+                .help("address to serve orcas on"))
+            .arg(Arg::with_name("base_port")
+                .short("p")
+                .long("base-port")
+                .takes_value(true)
+                .default_value("8877")
+                .help("port of the first orca, others are on the following ones"))
+            .arg(Arg::with_name("scenario")
+                .short("s")
+                .long("scenario")
+                .takes_value(true)
+                .help("yaml file with faults to inject: mismatches, slow responses, errors and restarts"))
+            .arg(Arg::with_name("hosts_file")
+                .long("hosts-file")
+                .takes_value(true)
+                .help("where to write simulated hosts list, to be used as `file` cluster source"))
+            .arg(Arg::with_name("tls_cert")
+                .long("tls-cert")
+                .takes_value(true)
+                .requires("tls_key")
+                .help("PEM certificate chain to serve orcas over https with"))
+            .arg(Arg::with_name("tls_key")
+                .long("tls-key")
+                .takes_value(true)
+                .requires("tls_cert")
+                .help("PEM private key of orcas certificate")))
         .get_matches();
 
-    let config = Config::new_from_default_files();
-    let context = Arc::new(Context{config, options});
-
-    //
-    // TODO: factory for hide construction details?
-    //
-    let cluster = Arc::new(SyncedCluster::new(Cluster::new()));
-    let orcas = Arc::new(SyncedOrcasPod::new(OrcasPod::new()));
-    let apps = Arc::new(SyncedApps::new(orca::Apps::new()));
-
-    let ctx_for_subscribe = Arc::clone(&context);
-    let cluster_for_subscribe = Arc::clone(&cluster);
-
-    std::thread::spawn(move || {
-
-        loop {
-            let mut core = Core::new().unwrap();
-            let unicorn = Unicorn::new(Service::new("unicorn", &core.handle()));
-
-            let cls = Arc::clone(&cluster_for_subscribe);
-            let cls1 = Arc::clone(&cluster_for_subscribe);
-
-            // TODO: Cocaine RT (unicorn) endpoints
-            let work = subscription(
-                &unicorn,
-                core.handle(),
-                &ctx_for_subscribe.config,
-                ctx_for_subscribe.get_listen_path(),
-                cls,
-            );
-
-            match core.run(work) {
-                // TODO: timestamp
-                Ok(_) => println!("cluster info updated"),
-                Err(e) => println!("error while obtaining cluster state {:?}", e)
-            };
-
-            cls1.write().unwrap().clear();
+    if let Some(options) = options.subcommand_matches("simulate") {
+        std::process::exit(simulate(options));
+    }
 
-            // sleep on subscribe error and try again
-            std::thread::sleep(std::time::Duration::new(SUSPEND_DURATION_SEC, 0));
+    let config = match Config::new_from_default_files() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("failed to load config: {:?}", e);
+            std::process::exit(EXIT_FAILURE);
         }
-    });
-
-    let cluster_for_gather = match context.options.is_present("dummy_data") {
-        true => Arc::new(SyncedCluster::new(make_dummy_cluster())),
-        false => Arc::clone(&cluster)
     };
 
-    let orcas_for_gather = Arc::clone(&orcas);
-    let apps_for_gather = Arc::clone(&apps);
-
-    std::thread::spawn(move || {
-        loop {
-            let mut core = Core::new().unwrap();
-            let client = hyper::client::Client::new(&core.handle());
-
-            let work = gather(
-                &client,
-                Arc::clone(&cluster_for_gather),
-                Arc::clone(&orcas_for_gather)
-            );
-
-            match core.run(work) {
-                Ok(_) => println!("orcas pod has been updated"),
-                Err(e) => println!("failed to request orcas with error {:?}", e),
-            };
-
-            let len = orcas_for_gather.read().unwrap().len();
-            println!("orcas pod size now is {}", len);
-
-            {   // Update apps stat.
-                let orcas = orcas_for_gather.read().unwrap();
-                let mut apps = apps_for_gather.write().unwrap();
+    let mut builder = RunnerBuilder::new(config);
+    builder.version(crate_version!());
 
-                apps.update(&orcas);
-            }
-
-            let len = apps_for_gather.read().unwrap().len();
-            println!("apps in global state {}", len);
+    if let Some(sources) = get_cluster_sources(&options) {
+        builder.clusters(sources);
+    }
+    if let Some(endpoints) = get_values(&options, "locator") {
+        builder.locator_endpoints(endpoints);
+    }
+    if let Some(addresses) = get_values(&options, "listen") {
+        builder.listen(addresses);
+    }
 
-            std::thread::sleep(std::time::Duration::new(POLL_DURATION_SEC, 0));
+    let exit_code = match builder.build() {
+        Ok(runner) => runner.run(),
+        Err(e) => {
+            println!("failed to start: {:?}", e);
+            EXIT_FAILURE
         }
-    });
-
-    let self_info = SelfInfo::new(crate_version!());
-
-    let model = web::Model {
-        cluster: Arc::clone(&cluster),
-        orcas: Arc::clone(&orcas),
-        apps: Arc::clone(&apps),
-        self_info
     };
 
-    loop {
-        let mut core = Core::new().unwrap();
-        let handle = core.handle();
-
-        // TODO: take from config
-        let address: SocketAddr = "[::1]:3141".parse().unwrap();
-        let listener = TcpListener::bind(&address, &handle).unwrap();
-
-        // TODO: hide details somehow.
-        let http = Http::new();
-        let server = listener.incoming().for_each(|(sock, addr)| {
-            // TODO: static file folder from config.
-            let web = WebApi::new(&handle, model.clone(), "assets");
-            http.bind_connection(&handle, sock, addr, web);
-            Ok(())
-        });
-
-        match core.run(server) {
-            Ok(_) => println!("web service exited normally"),
-            Err(e) => println!("error in web service {:?}", e)
-        };
-
-        std::thread::sleep(std::time::Duration::new(SUSPEND_DURATION_SEC, 0));
-    }
+    // Note: there is no persisted state yet, only buffered output to flush.
+    let _ = std::io::stdout().flush();
+
+    std::process::exit(exit_code);
 }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 use resources::Endpoint;
 
@@ -19,7 +19,7 @@ pub enum WebHandler {
     Metrics
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppState {
     profile: String,
     state: String,
@@ -30,7 +30,7 @@ pub struct AppState {
     time_stamp: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommittedState {
     // mapping: app -> state
     state: HashMap<String, AppState>,
@@ -65,10 +65,10 @@ pub type WorkersDistribution = HashMap<String, i64>;
 
 // mapping: hostname -> Orca struct
 pub type OrcasPod = HashMap<String, OrcaRecord>;
-pub type SyncedOrcasPod = RwLock<OrcasPod>;
+pub type SyncedOrcasPod = RwLock<Arc<OrcasPod>>;
 
 pub type Apps = HashMap<String, AppStat>;
-pub type SyncedApps = RwLock<Apps>;
+pub type SyncedApps = RwLock<Arc<Apps>>;
 
 // TODO: support of int values (counters).
 pub type Metrics = HashMap<String, f64>;
@@ -76,9 +76,11 @@ pub type Metrics = HashMap<String, f64>;
 
 pub trait AppsTrait {
     fn update(&mut self, pod: &OrcasPod);
+    // Adds apps of pod to already collected ones.
+    fn merge(&mut self, pod: &OrcasPod);
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Info {
     pub uptime: i64,
     pub version: String,
@@ -113,7 +115,7 @@ impl WorkersCount {
 
 pub type Distribution = HashMap<String, WorkersCount>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Orca {
     pub endpoints: Vec<Endpoint>,
     pub info: Info,
@@ -125,7 +127,7 @@ pub struct Orca {
     pub committed_state: CommittedState,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OrcaRecord {
     pub orca: Orca,
     pub update_timestamp: u64,
@@ -138,7 +140,7 @@ pub struct OrcaInState {
     pub version: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppStat {
     pub total_workers: i64,
     pub hosts: HashMap<String, WorkersCount>,
@@ -154,7 +156,10 @@ impl AppsTrait for Apps {
 
     fn update(&mut self, pod: &OrcasPod) {
         self.clear();
+        self.merge(pod);
+    }
 
+    fn merge(&mut self, pod: &OrcasPod) {
         for (host, orca) in pod {
             for (app, dist) in orca.orca.distribution.iter()
                 .filter(|&(_, dist)| dist.nonempty()) {
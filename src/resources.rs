@@ -1,4 +1,4 @@
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Endpoint(pub String, pub u16);
 
 impl Endpoint {
@@ -15,5 +15,17 @@ pub struct Resources {
 pub struct NodeInfo {
     pub hostname: String,
     pub resources: Resources,
-    pub endpoints: Vec<Endpoint>
+    pub endpoints: Vec<Endpoint>,
+    // Not stored in unicorn, set by zorca to name of cluster node was found in.
+    #[serde(default)]
+    pub cluster: String,
+    // Unicorn version node info was fetched with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unicorn_version: Option<i64>,
+    // Set once subscription is lost and node is kept as last known one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_since: Option<u64>,
+    // Not stored in unicorn, orca port if it differs from `orca_web` one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orca_port: Option<u16>,
 }
@@ -0,0 +1,558 @@
+//
+// Zorca service: cluster discovery, orcas gathering and web api loops over
+// shared state, wired from config.
+//
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::Future;
+use futures::future::{self, Either, Loop};
+
+use tokio_core::reactor::{Core, Handle};
+
+use access::{Authenticator, spawn_ticket_refresh};
+use acceptor::{SharedAcceptor, make_acceptor, spawn_reload_on_sighup};
+use backoff::Backoff;
+use cache::ResponseCache;
+use config::{ClusterSource, Config, Locator, OnFailure, OrcaAuth, OrcaWeb};
+use connector;
+use discovery::{Discovery, make_discovery};
+use engine::{ClusterView, ClusterViews, clear_cluster, gather, mark_stale, orca_auth_header};
+use errors::CombinedError;
+use health::{HealthState, Subsystem, SyncedHealth, now_secs};
+use lifecycle::{EXIT_FAILURE, EXIT_OK, Lifecycle, pause, stop_signals, wait_stop};
+use orca::{self, AppsTrait, SyncedApps};
+use secure::{SharedProxy, SyncedTicketInfo, TicketInfo, make_ticket_service};
+use server::{self, Listener, WebContext};
+use snapshot::Publisher;
+use web::{Model, SelfInfo};
+
+
+const SUSPEND_DURATION_SEC: u64 = 10;
+const POLL_DURATION_SEC: u64 = 10;
+const WEB_TICKET_REFRESH_SEC: u64 = 60;
+const DRAIN_TIMEOUT_SEC: u64 = 10;
+const DEFAULT_STATIC_PATH: &str = "assets";
+
+
+/// Settings of zorca service on top of config, e.g. from command line.
+pub struct RunnerBuilder {
+    config: Config,
+    clusters: Option<Vec<ClusterSource>>,
+    locator_endpoints: Option<Vec<String>>,
+    listen: Option<Vec<String>>,
+    version: String,
+    static_path: String,
+}
+
+impl RunnerBuilder {
+    pub fn new(config: Config) -> RunnerBuilder {
+        RunnerBuilder {
+            config,
+            clusters: None,
+            locator_endpoints: None,
+            listen: None,
+            version: String::new(),
+            static_path: DEFAULT_STATIC_PATH.to_string(),
+        }
+    }
+
+    /// Clusters to monitor instead of config ones.
+    pub fn clusters(&mut self, clusters: Vec<ClusterSource>) -> &mut Self {
+        self.clusters = Some(clusters);
+        self
+    }
+
+    /// Locator endpoints to use instead of config ones, including cluster
+    /// specific, e.g. to point to a local fake locator.
+    pub fn locator_endpoints(&mut self, endpoints: Vec<String>) -> &mut Self {
+        self.locator_endpoints = Some(endpoints);
+        self
+    }
+
+    /// Web api addresses to listen on instead of config ones.
+    pub fn listen(&mut self, addresses: Vec<String>) -> &mut Self {
+        self.listen = Some(addresses);
+        self
+    }
+
+    /// Version reported by web api.
+    pub fn version(&mut self, version: &str) -> &mut Self {
+        self.version = version.to_string();
+        self
+    }
+
+    pub fn static_path(&mut self, path: &str) -> &mut Self {
+        self.static_path = path.to_string();
+        self
+    }
+
+    /// Checks settings and binds web api listeners, so misconfiguration is
+    /// reported before anything is started.
+    pub fn build(self) -> Result<Runner, CombinedError> {
+        let RunnerBuilder { config, clusters, locator_endpoints, listen, version, static_path } = self;
+
+        let sources = clusters.unwrap_or_else(|| config.clusters.clone());
+        if sources.is_empty() {
+            return Err(CombinedError::ConfigError("no clusters to monitor, set `clusters` config section".to_string()));
+        }
+
+        let mut names = BTreeSet::new();
+        for source in &sources {
+            if !names.insert(source.name.clone()) {
+                return Err(CombinedError::ConfigError(format!("duplicate cluster name {}", source.name)));
+            }
+        }
+
+        // Ticket sent to orcas could be replayed by any of them to web api.
+        let ticket_to_orcas = match config.orca_web.auth {
+            Some(OrcaAuth::Ticket) => true,
+            _ => false
+        };
+        if ticket_to_orcas && config.web.auth.as_ref().map_or(false, |auth| auth.ticket_role.is_some()) {
+            let error = "web auth `ticket` role can't be used along with `ticket` orca auth".to_string();
+            return Err(CombinedError::ConfigError(error));
+        }
+
+        let auth = match config.web.auth {
+            Some(ref auth) => Some(Rc::new(Authenticator::new(auth)?)),
+            None => None
+        };
+
+        let tls = match config.web.tls {
+            Some(ref tls) => Some(Rc::new(RefCell::new(make_acceptor(tls)?))),
+            None => None
+        };
+
+        let listeners = server::bind_all(listen.as_ref().unwrap_or(&config.web.listen))?;
+
+        let forced_locator = locator_endpoints.is_some();
+        let locator = match locator_endpoints {
+            Some(endpoints) => Locator { endpoints, ..config.locator.clone() },
+            None => config.locator.clone()
+        };
+
+        Ok(Runner {
+            config: Arc::new(config),
+            sources,
+            locator,
+            forced_locator,
+            listeners,
+            auth,
+            tls,
+            version,
+            static_path,
+        })
+    }
+}
+
+/// Zorca service ready to run.
+pub struct Runner {
+    config: Arc<Config>,
+    sources: Vec<ClusterSource>,
+    locator: Locator,
+    // Whether locator endpoints are overridden for all clusters.
+    forced_locator: bool,
+    listeners: Vec<Listener>,
+    auth: Option<Rc<Authenticator>>,
+    tls: Option<SharedAcceptor>,
+    version: String,
+    static_path: String,
+}
+
+// Runner along with state shared by all loops, everything is run on a
+// single event loop.
+struct Context {
+    runner: Runner,
+    handle: Handle,
+    clusters: Arc<ClusterViews>,
+    apps: Arc<SyncedApps>,
+    ticket_info: Arc<SyncedTicketInfo>,
+    // Single proxy with its cache for all loops.
+    proxy: SharedProxy,
+    lifecycle: Arc<Lifecycle>,
+    health: Arc<SyncedHealth>,
+    publisher: Publisher,
+}
+
+type LoopFuture = Box<Future<Item=(), Error=()>>;
+
+impl Runner {
+    fn cluster_locator(&self, source: &ClusterSource) -> Locator {
+        match self.forced_locator {
+            true => self.locator.clone(),
+            false => self.config.locator.for_cluster(source)
+        }
+    }
+
+    /// Runs until SIGTERM or SIGINT, returns process exit code.
+    pub fn run(self) -> i32 {
+        let mut core = Core::new().unwrap();
+
+        let mut clusters = ClusterViews::new();
+        for source in &self.sources {
+            clusters.insert(source.name.clone(), ClusterView::new());
+        }
+        let clusters = Arc::new(clusters);
+
+        // Apps of all clusters merged.
+        let apps = Arc::new(SyncedApps::new(Arc::new(orca::Apps::new())));
+
+        let ticket_required = self.config.secure.as_ref().map_or(false, |secure| secure.issues_tickets());
+        let cluster_names: Vec<_> = self.sources.iter().map(|source| source.name.clone()).collect();
+
+        let ticket_info = Arc::new(SyncedTicketInfo::new(TicketInfo::new()));
+        let proxy = make_ticket_service(&self.locator, &core.handle(), &self.config, Arc::clone(&ticket_info));
+
+        let ctx = Rc::new(Context {
+            handle: core.handle(),
+            publisher: Publisher::new(Arc::clone(&clusters), Arc::clone(&apps)),
+            clusters,
+            apps,
+            ticket_info,
+            proxy,
+            lifecycle: Arc::new(Lifecycle::new()),
+            health: Arc::new(SyncedHealth::new(HealthState::new(ticket_required, &cluster_names))),
+            runner: self,
+        });
+
+        let lifecycle = Arc::clone(&ctx.lifecycle);
+        let signals = stop_signals(&ctx.handle).then(move |signal| {
+            match signal {
+                Ok(signal) => {
+                    println!("got signal {}, shutting down", signal);
+                    lifecycle.stop();
+                },
+                Err(e) => println!("failed to wait for stop signals {:?}", e)
+            };
+            Ok::<_, ()>(())
+        });
+        ctx.handle.spawn(signals);
+
+        let mut loops = Vec::with_capacity(ctx.runner.sources.len() + 1);
+        for source in &ctx.runner.sources {
+            loops.push(subscription_loop(Rc::clone(&ctx), source));
+        }
+        loops.push(gather_loop(Rc::clone(&ctx)));
+
+        let web_context = make_web_context(&ctx);
+        let web = web_loop(Rc::clone(&ctx), Rc::clone(&web_context));
+
+        // Loops are ended on shutdown only.
+        let _ = core.run(web.join(future::join_all(loops)));
+
+        let timeout = Duration::new(DRAIN_TIMEOUT_SEC, 0);
+        if !server::drain(&mut core, &web_context, timeout) {
+            println!("{} request(s) were not completed in time", web_context.in_flight.get());
+            return EXIT_FAILURE;
+        }
+
+        EXIT_OK
+    }
+}
+
+fn subscription_loop(ctx: Rc<Context>, source: &ClusterSource) -> LoopFuture {
+    let name = source.name.clone();
+    let policy = ctx.runner.config.subscription.clone();
+    let discovery: Rc<Box<Discovery>> = Rc::new(make_discovery(
+        source,
+        ctx.runner.cluster_locator(source),
+        Rc::clone(&ctx.proxy),
+        Arc::clone(&ctx.health),
+        ctx.publisher.clone(),
+    ));
+
+    let backoff = Backoff::new(policy.backoff.clone());
+    let cluster = name.clone();
+
+    let work = future::loop_fn(backoff, move |mut backoff| {
+        let (ctx, name, policy) = (Rc::clone(&ctx), name.clone(), policy.clone());
+        let view = ctx.clusters[&name].clone();
+        let subsystem = Subsystem::Subscription(name.clone());
+        let started = now_secs();
+        ctx.health.write().unwrap().beat(&subsystem);
+
+        let work = discovery.run(&ctx.handle, &view)
+            .select(wait_stop(&ctx.lifecycle))
+            .map(|_| ())
+            .map_err(|(e, _)| e);
+
+        work.then(move |result| {
+            let error = match result {
+                // TODO: timestamp
+                Ok(_) => { println!("cluster {} info updated", name); "subscription has ended".to_string() },
+                Err(CombinedError::LocatorError(e)) => {
+                    println!("locator for cluster {} is unavailable: {}", name, e);
+                    format!("locator is unavailable: {}", e)
+                },
+                Err(e) => { println!("error while obtaining cluster {} state {:?}", name, e); format!("{:?}", e) }
+            };
+
+            let established = {
+                let mut health = ctx.health.write().unwrap();
+                health.failure(&subsystem, error);
+                health.subscriptions.get(&name).map_or(false, |state| state.last_success >= started)
+            };
+
+            if ctx.lifecycle.is_stopping() {
+                return Either::A(future::ok(Loop::Break(())));
+            }
+
+            // Backoff is started over if subscription has worked for a while.
+            if established {
+                backoff.reset();
+            }
+
+            let now = now_secs();
+            let stale_since = match policy.on_failure {
+                OnFailure::Keep => mark_stale(&view.cluster, now),
+                OnFailure::Clear => None,
+            };
+
+            let to_clear = match (stale_since, policy.max_stale_sec) {
+                (Some(since), Some(max_stale)) => now.saturating_sub(since) > max_stale,
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+
+            if to_clear {
+                clear_cluster(&view, &name, now);
+            }
+            ctx.publisher.publish_nodes(&name);
+
+            // wait on subscribe error and try again
+            let delay = backoff.next();
+            println!("resubscribing to cluster {} in {} sec", name, delay.as_secs());
+            ctx.health.write().unwrap().backoff(&subsystem, delay);
+
+            let resume = pause(&ctx.handle, delay, &ctx.lifecycle)
+                .then(move |_| Ok::<_, ()>(Loop::Continue(backoff)));
+            Either::B(resume)
+        })
+    });
+
+    Box::new(work.map(move |_| println!("subscription to cluster {} has been stopped", cluster)))
+}
+
+// Gather state kept between cycles.
+struct Gatherer {
+    ctx: Rc<Context>,
+    // mapping: cluster -> orca settings with cluster overrides
+    orca_webs: HashMap<String, OrcaWeb>,
+}
+
+fn gather_loop(ctx: Rc<Context>) -> LoopFuture {
+    let orca_webs = ctx.runner.sources.iter()
+        .map(|source| (source.name.clone(), ctx.runner.config.orca_web_for(source)))
+        .collect();
+
+    let gatherer = Rc::new(Gatherer { ctx, orca_webs });
+
+    let work = future::loop_fn((), move |_| {
+        let gatherer = Rc::clone(&gatherer);
+        let lifecycle = Arc::clone(&gatherer.ctx.lifecycle);
+        let stop = wait_stop(&lifecycle).then(|_| Ok::<_, ()>(None));
+
+        gather_cycle(&gatherer).map(Some).select(stop).then(move |result| {
+            let delay = match result {
+                Ok((Some(delay), _)) if !lifecycle.is_stopping() => delay,
+                _ => return Either::A(future::ok(Loop::Break(())))
+            };
+
+            let resume = pause(&gatherer.ctx.handle, delay, &lifecycle)
+                .then(|_| Ok::<_, ()>(Loop::Continue(())));
+            Either::B(resume)
+        })
+    });
+
+    Box::new(work.map(|_| println!("orcas gathering has been stopped")))
+}
+
+// Resolves to delay till next cycle.
+fn gather_cycle(gatherer: &Rc<Gatherer>) -> Box<Future<Item=Duration, Error=()>> {
+    let ctx = Rc::clone(&gatherer.ctx);
+    let prepare = Rc::clone(gatherer);
+
+    let prepared = future::lazy(move || {
+        let ctx = &prepare.ctx;
+
+        let clients = ctx.clusters.keys()
+            .map(|name| connector::make_client(&ctx.handle, &prepare.orca_webs[name])
+                .map(|client| (name.clone(), client)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                println!("failed to create orca client with error {:?}", e);
+                e
+            })?;
+
+        let auth = orca_auth_header(&ctx.runner.config.orca_web.auth, &mut **ctx.proxy.borrow_mut())
+            .map_err(|e| {
+                println!("failed to obtain orca credentials with error {:?}", e);
+                e
+            });
+
+        Ok::<_, CombinedError>(auth.map(move |auth| (clients, auth)))
+    })
+    .flatten();
+
+    let gatherer = Rc::clone(gatherer);
+
+    let cycle = prepared.then(move |prepared| {
+        let (clients, auth) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                ctx.health.write().unwrap().failure(&Subsystem::Gather, format!("{:?}", e));
+                return Either::A(future::ok(Duration::new(SUSPEND_DURATION_SEC, 0)));
+            }
+        };
+
+        // Failure of one cluster doesn't abort gather of others.
+        let work: Vec<_> = clients.into_iter()
+            .map(|(name, client)| {
+                let view = &ctx.clusters[&name];
+                gather(
+                    &client,
+                    Arc::clone(&view.cluster),
+                    Arc::clone(&view.orcas),
+                    Arc::clone(&view.breakers),
+                    ctx.runner.config.breaker.clone(),
+                    gatherer.orca_webs[&name].clone(),
+                    auth.clone(),
+                )
+                .then(move |result| {
+                    if let Err(ref e) = result {
+                        println!("failed to gather orcas of cluster {} with error {:?}", name, e);
+                    }
+                    Ok::<_, CombinedError>(result.ok())
+                })
+            })
+            .collect();
+        let work = future::join_all(work).and_then(|gathered| {
+            match gathered.iter().any(Option::is_some) || gathered.is_empty() {
+                true => Ok(gathered.into_iter().map(|hosts| hosts.unwrap_or(0)).sum::<usize>()),
+                false => Err(CombinedError::Other("failed to gather orcas of every cluster".to_string())),
+            }
+        });
+
+        let started = Instant::now();
+
+        let done = work.then(move |result| {
+            ctx.health.write().unwrap().gather_done(started.elapsed());
+
+            // Cycle without a single host gathered isn't a success, e.g. if
+            // no hosts are discovered yet.
+            match result {
+                Ok(0) => {
+                    println!("no orcas have been gathered");
+                    ctx.health.write().unwrap().failure(&Subsystem::Gather, "no hosts gathered".to_string());
+                },
+                Ok(hosts) => {
+                    println!("orcas pod has been updated from {} host(s)", hosts);
+                    ctx.health.write().unwrap().success(&Subsystem::Gather);
+                },
+                Err(e) => {
+                    println!("failed to request orcas with error {:?}", e);
+                    ctx.health.write().unwrap().failure(&Subsystem::Gather, format!("{:?}", e));
+                },
+            };
+
+            {   // Update apps stat, per cluster and merged.
+                let mut merged = orca::Apps::new();
+
+                for (name, view) in ctx.clusters.iter() {
+                    let orcas = view.orcas.read().unwrap();
+                    println!("orcas pod size of cluster {} now is {}", name, orcas.len());
+
+                    let mut apps = orca::Apps::new();
+                    apps.update(&orcas);
+                    merged.merge(&orcas);
+
+                    *view.apps.write().unwrap() = Arc::new(apps);
+                }
+
+                *ctx.apps.write().unwrap() = Arc::new(merged);
+            }
+
+            let len = ctx.apps.read().unwrap().len();
+            println!("apps in global state {}", len);
+
+            ctx.publisher.publish_gathered();
+
+            Ok::<_, ()>(Duration::new(POLL_DURATION_SEC, 0))
+        });
+
+        Either::B(done)
+    });
+
+    Box::new(cycle)
+}
+
+fn make_web_context(ctx: &Context) -> Rc<WebContext> {
+    let runner = &ctx.runner;
+    let self_info = SelfInfo::new(&runner.version, &runner.sources, runner.config.to_redacted_json());
+
+    let model = Model {
+        snapshots: ctx.publisher.clone(),
+        ticket: Arc::clone(&ctx.ticket_info),
+        lifecycle: Arc::clone(&ctx.lifecycle),
+        health: Arc::clone(&ctx.health),
+        self_info
+    };
+
+    Rc::new(WebContext {
+        model,
+        auth: runner.auth.clone(),
+        tls: runner.tls.clone(),
+        static_path: runner.static_path.clone(),
+        in_flight: Rc::new(Cell::new(0)),
+        cache: Rc::new(RefCell::new(ResponseCache::new())),
+        cors: runner.config.web.cors.clone().map(Rc::new),
+    })
+}
+
+// Serves web api until shutdown, restarted after a pause on failure.
+fn web_loop(ctx: Rc<Context>, web_context: Rc<WebContext>) -> LoopFuture {
+    if let (Some(tls), Some(acceptor)) = (ctx.runner.config.web.tls.clone(), ctx.runner.tls.clone()) {
+        spawn_reload_on_sighup(&ctx.handle, tls, acceptor);
+    }
+
+    if let Some(ref auth) = ctx.runner.auth {
+        if auth.accepts_ticket() {
+            let period = Duration::new(WEB_TICKET_REFRESH_SEC, 0);
+            if let Err(e) = spawn_ticket_refresh(&ctx.handle, Rc::clone(&ctx.proxy), Arc::clone(&ctx.ticket_info), Rc::clone(auth), period) {
+                println!("failed to start web api ticket refresh {:?}", e);
+            }
+        }
+    }
+
+    let work = future::loop_fn((), move |_| {
+        let ctx = Rc::clone(&ctx);
+        let stop = wait_stop(&ctx.lifecycle);
+
+        // Listeners are dropped on stop, so no new connections are accepted.
+        let served: Box<Future<Item=(), Error=CombinedError>> = match server::serve(&ctx.handle, &ctx.runner.listeners, Rc::clone(&web_context)) {
+            Ok(served) => served,
+            Err(e) => Box::new(future::err(e))
+        };
+
+        served.select(stop).map(|_| ()).map_err(|(e, _)| e).then(move |result| {
+            match result {
+                Ok(_) => println!("web service exited normally"),
+                Err(e) => println!("error in web service {:?}", e)
+            };
+
+            if ctx.lifecycle.is_stopping() {
+                return Either::A(future::ok(Loop::Break(())));
+            }
+
+            let resume = pause(&ctx.handle, Duration::new(SUSPEND_DURATION_SEC, 0), &ctx.lifecycle)
+                .then(|_| Ok::<_, ()>(Loop::Continue(())));
+            Either::B(resume)
+        })
+    });
+
+    Box::new(work)
+}
@@ -17,11 +17,7 @@ use serde::Deserialize;
 use std::collections::HashMap;
 
 use errors::CombinedError;
-use engine::{Cluster, SubscribeMessage};
-use resources::{NodeInfo, Resources, Endpoint};
-
-
-const LOCAL_IPV6: &str = "::1";
+use engine::SubscribeMessage;
 
 
 #[derive(Deserialize, Debug)]
@@ -124,70 +120,3 @@ where
 
     Box::new(future)
 }
-
-pub fn make_dummy_cluster() -> Cluster {
-    let mut cluster = Cluster::new();
-
-    fn make_dummy_node_info(hostname: &str, resources: Resources, ep: Endpoint) -> NodeInfo {
-        let endpoints = vec![ep];
-        let hostname = String::from(hostname);
-        NodeInfo{hostname, resources, endpoints}
-    }
-
-    cluster.insert(
-        "a".to_string(),
-        make_dummy_node_info(
-            "host1.net",
-            Resources{cpu: 100, mem: 1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-    cluster.insert(
-        "b".to_string(),
-        make_dummy_node_info(
-            "host2.net",
-            Resources{cpu: 2*100, mem: 2*1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-    cluster.insert(
-        "c".to_string(),
-        make_dummy_node_info(
-            "host3.net",
-            Resources{cpu: 3*100, mem: 3*1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-    cluster.insert(
-        "d".to_string(),
-        make_dummy_node_info(
-            "host4.net",
-            Resources{cpu: 9*100, mem: 12*1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-    cluster.insert(
-        "e".to_string(),
-        make_dummy_node_info(
-            "host5.net",
-            Resources{cpu: 8*100, mem: 7*1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-
-    cluster.insert(
-        "f".to_string(),
-        make_dummy_node_info(
-            "host6.net",
-            Resources{cpu: 2*100, mem: 1*1024},
-            Endpoint(LOCAL_IPV6.to_string(), 8877)
-        )
-    );
-
-    cluster
-}
@@ -1,19 +1,74 @@
 use cocaine::service::Tvm;
-use cocaine::{Error, Service};
+use cocaine::Service;
 use cocaine::service::tvm::Grant;
 
 use futures;
-use futures::future::{Future};
+use futures::Stream;
+use futures::future::{Future, Shared};
+
+use hyper;
+use hyper::header::{ContentLength, ContentType};
+
+use serde_json;
+
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_process::CommandExt;
 
 use time;
 
-use config::{Config, Secure};
+use config::{Config, Locator, OAuth2Credentials, SecureBackend, TvmCredentials};
+use errors::CombinedError;
+use connector::{OrcaClient, make_default_client};
+use locator::{make_resolved_service, resolve_async};
+
 use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+use std::time::Duration;
+
+// Ticket is refreshed in background when this share of its lifetime is left.
+const REFRESH_AHEAD_PERCENT: i64 = 20;
+// Used if token endpoint doesn't report token lifetime.
+const DEFAULT_OAUTH2_EXPIRE_SEC: i64 = 10 * 60;
+// Credential helper is killed if it takes longer.
+const COMMAND_TIMEOUT_SEC: u64 = 30;
 
 pub trait SecureProxy {
-    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = Error>>;
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>>;
+}
+
+/// The only proxy of process, shared by all of its loops, so is its cache.
+pub type SharedProxy = Rc<RefCell<Box<SecureProxy>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketInfo {
+    pub last_updated: i64,
+    pub age: i64,
+    pub refresh_count: u64,
+    pub last_error: Option<String>,
+    pub last_error_time: i64,
+    // Time cached ticket stops being served at, zero if it isn't cached.
+    pub expires_at: i64,
+}
+
+impl TicketInfo {
+    pub fn new() -> TicketInfo {
+        TicketInfo {
+            last_updated: 0,
+            age: 0,
+            refresh_count: 0,
+            last_error: None,
+            last_error_time: 0,
+            expires_at: 0,
+        }
+    }
 }
 
+pub type SyncedTicketInfo = RwLock<TicketInfo>;
+
 struct PromiscuousProxy;
 
 impl PromiscuousProxy {
@@ -22,66 +77,173 @@ impl PromiscuousProxy {
     }
 }
 
-struct TvmProxy {
-    tvm: Tvm,
+type SharedTicket = Shared<Box<Future<Item = String, Error = String>>>;
+
+struct TicketCache {
+    ticket: Option<String>,
     last_updated: i64,
+    // Refresh in flight, shared by all concurrent requests for a ticket.
+    pending: Option<SharedTicket>,
+}
+
+struct TvmProxy {
+    locator: Locator,
+    // Resolved on first refresh, dropped on failure to be resolved again.
+    service: Rc<RefCell<Option<Service>>>,
+    handle: Handle,
     ticket_expire_sec: Option<i64>,
-    cached_ticket: Rc<Option<String>>,
-    secure: Secure,
+    cache: Rc<RefCell<TicketCache>>,
+    md: String,
+    credentials: TvmCredentials,
+    stat: Arc<SyncedTicketInfo>,
 }
 
 impl TvmProxy {
-    pub fn new(service: Service, ticket_expire_sec: &Option<i64>, secure: &Secure) -> TvmProxy {
-        TvmProxy{
-            tvm: Tvm::new(service),
+    pub fn new(
+        locator: &Locator,
+        handle: &Handle,
+        ticket_expire_sec: &Option<i64>,
+        md: &str,
+        credentials: &TvmCredentials,
+        stat: Arc<SyncedTicketInfo>) -> TvmProxy
+    {
+        let cache = TicketCache {
+            ticket: None,
             last_updated: 0,
+            pending: None,
+        };
+
+        TvmProxy{
+            locator: locator.clone(),
+            service: Rc::new(RefCell::new(None)),
+            handle: handle.clone(),
             ticket_expire_sec: ticket_expire_sec.clone(),
-            cached_ticket: Rc::new(None),
-            secure: secure.clone(),
+            cache: Rc::new(RefCell::new(cache)),
+            md: md.to_string(),
+            credentials: credentials.clone(),
+            stat,
         }
     }
 
-    pub fn ticket(&mut self) -> Box<Future<Item = String, Error = Error>> {
-        match self.ticket_expire_sec {
-            Some(to_expire) =>
-                if self.last_updated < time::get_time().sec - to_expire {
-                    self.update_and_get_cached()
-                } else {
-                    self.get_or_fetch()
-                },
-            None => self.fetch_ticket()
+    pub fn ticket(&mut self) -> Box<Future<Item = String, Error = CombinedError>> {
+        let to_expire = match self.ticket_expire_sec {
+            Some(to_expire) => to_expire,
+            None => return self.refresh()
+        };
+
+        let now = time::get_time().sec;
+        let (ticket, age, is_pending) = {
+            let cache = self.cache.borrow();
+            (cache.ticket.clone(), now - cache.last_updated, cache.pending.is_some())
+        };
+
+        match ticket {
+            Some(ticket) if age < to_expire => {
+                let refresh_after = to_expire - to_expire * REFRESH_AHEAD_PERCENT / 100;
+
+                if age >= refresh_after && !is_pending {
+                    println!("ticket is about to expire, refreshing in background");
+                    let refresh = self.refresh().then(|_| Ok(()));
+                    self.handle.spawn(refresh);
+                }
+
+                Box::new(futures::future::ok(ticket))
+            },
+            _ => self.refresh()
         }
     }
 
-    fn get_or_fetch(&self) -> Box<Future<Item = String, Error = Error>> {
-        if let Some(ref token) = *self.cached_ticket {
-            Box::new(futures::future::ok(token.clone()))
-        } else {
-            self.update_and_get_cached()
+    fn refresh(&self) -> Box<Future<Item = String, Error = CombinedError>> {
+        if let Some(ref pending) = self.cache.borrow().pending {
+            return Self::wait(pending.clone());
         }
-    }
 
-    fn update_and_get_cached(&self) -> Box<Future<Item = String, Error = Error>> {
-        let mut cached_ticket = Rc::clone(&self.cached_ticket);
-        let future = self.fetch_ticket().and_then(move |token| {
-            cached_ticket = Rc::new(Some(token.clone()));
-            Ok(token)
+        let cache = Rc::clone(&self.cache);
+        let stat = Arc::clone(&self.stat);
+        let to_expire = self.ticket_expire_sec;
+
+        let fetch = self.fetch_ticket().then(move |result| {
+            let now = time::get_time().sec;
+
+            let mut cache = cache.borrow_mut();
+            let mut stat = stat.write().unwrap();
+
+            cache.pending = None;
+
+            match result {
+                Ok(ticket) => {
+                    cache.ticket = Some(ticket.clone());
+                    cache.last_updated = now;
+
+                    stat.last_updated = now;
+                    stat.refresh_count += 1;
+                    stat.expires_at = to_expire.map_or(0, |to_expire| now + to_expire);
+
+                    Ok(ticket)
+                },
+                Err(e) => {
+                    let error = format!("{:?}", e);
+                    println!("failed to refresh ticket {}", error);
+
+                    stat.last_error = Some(error.clone());
+                    stat.last_error_time = now;
+
+                    Err(error)
+                }
+            }
         });
 
+        let fetch: Box<Future<Item = String, Error = String>> = Box::new(fetch);
+        let shared = fetch.shared();
+
+        self.cache.borrow_mut().pending = Some(shared.clone());
+        Self::wait(shared)
+    }
+
+    fn wait(shared: SharedTicket) -> Box<Future<Item = String, Error = CombinedError>> {
+        let future = shared
+            .map(|ticket| (*ticket).clone())
+            .map_err(|e| CombinedError::Other((*e).clone()));
+
         Box::new(future)
     }
 
-    fn fetch_ticket(&self) -> Box<Future<Item = String, Error = Error>> {
-        let grant: Grant = self.secure.grant.clone().unwrap_or(Grant::ClientCredentials);
-        let future = self.tvm.ticket(self.secure.client_id as u32, &self.secure.client_secret, &grant);
+    fn fetch_ticket(&self) -> Box<Future<Item = String, Error = CombinedError>> {
+        let service: Box<Future<Item = Service, Error = CombinedError>> = match *self.service.borrow() {
+            Some(ref service) => Box::new(futures::future::ok(service.clone())),
+            None => {
+                let (name, handle, cached) = (self.locator.tvm_service.clone(), self.handle.clone(), Rc::clone(&self.service));
+                let resolved = resolve_async(&self.locator).and_then(move |addrs| {
+                    let service = make_resolved_service(&name, &handle, &addrs)?;
+                    *cached.borrow_mut() = Some(service.clone());
+                    Ok(service)
+                });
+                Box::new(resolved)
+            }
+        };
+
+        let credentials = self.credentials.clone();
+        let cached = Rc::clone(&self.service);
+
+        let future = service
+            .and_then(move |service| {
+                let grant: Grant = credentials.grant.clone().unwrap_or(Grant::ClientCredentials);
+                Tvm::new(service)
+                    .ticket(credentials.client_id as u32, &credentials.client_secret, &grant)
+                    .map_err(CombinedError::CocaineError)
+            })
+            .map_err(move |e| {
+                *cached.borrow_mut() = None;
+                e
+            });
 
         Box::new(future)
     }
 }
 
 impl SecureProxy for TvmProxy {
-    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = Error>> {
-        let ty = self.secure.get_mod();
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>> {
+        let ty = self.md.clone();
         let header = self.ticket().and_then(move |token| {
             Ok(Some(format!("{} {}", ty, token)))
         });
@@ -90,19 +252,356 @@ impl SecureProxy for TvmProxy {
 }
 
 impl SecureProxy for PromiscuousProxy {
-    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = Error>> {
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>> {
         Box::new(futures::future::ok(None))
     }
 }
 
 
-pub fn make_ticket_service(service: Service, config: &Config) -> Box<SecureProxy>
+//
+// Token is read from file on each request, so it could be rotated by
+// external tools without zorca restart.
+//
+struct StaticTokenProxy {
+    path: String,
+    scheme: String,
+}
+
+impl StaticTokenProxy {
+    pub fn new(path: &str, scheme: &str) -> StaticTokenProxy {
+        StaticTokenProxy {
+            path: path.to_string(),
+            scheme: scheme.to_string(),
+        }
+    }
+}
+
+impl SecureProxy for StaticTokenProxy {
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>> {
+        let mut token = String::new();
+        let result = File::open(&self.path)
+            .and_then(|mut fl| fl.read_to_string(&mut token))
+            .map(|_| Some(format!("{} {}", self.scheme, token.trim())))
+            .map_err(CombinedError::IOError);
+
+        Box::new(futures::future::result(result))
+    }
+}
+
+
+#[derive(Deserialize)]
+struct OAuth2Token {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+// Token along with the moment it should be refreshed at.
+struct CachedToken {
+    token: String,
+    expire_at: i64,
+}
+
+type SharedToken = Shared<Box<Future<Item = String, Error = String>>>;
+
+// Token of external source, refreshed at most once at a time, concurrent
+// requests wait for the same refresh.
+struct TokenCache {
+    cached: Option<CachedToken>,
+    pending: Option<SharedToken>,
+    stat: Arc<SyncedTicketInfo>,
+}
+
+type SharedTokenCache = Rc<RefCell<TokenCache>>;
+
+impl TokenCache {
+    fn new(stat: Arc<SyncedTicketInfo>) -> SharedTokenCache {
+        Rc::new(RefCell::new(TokenCache { cached: None, pending: None, stat }))
+    }
+}
+
+// Returns cached token, `fetch` is called for fresh one along with its
+// expiration time only if neither valid token nor refresh in flight.
+fn cached_token<F>(cache: &SharedTokenCache, fetch: F) -> Box<Future<Item = String, Error = CombinedError>>
+where
+    F: FnOnce() -> Box<Future<Item = CachedToken, Error = CombinedError>>
+{
+    let now = time::get_time().sec;
+
+    {
+        let cache = cache.borrow();
+
+        if let Some(ref cached) = cache.cached {
+            if now < cached.expire_at {
+                return Box::new(futures::future::ok(cached.token.clone()));
+            }
+        }
+
+        if let Some(ref pending) = cache.pending {
+            return wait_token(pending.clone());
+        }
+    }
+
+    let shared_cache = Rc::clone(cache);
+    let refresh = fetch().then(move |result| {
+        let now = time::get_time().sec;
+
+        let mut cache = shared_cache.borrow_mut();
+        cache.pending = None;
+
+        let synced_stat = Arc::clone(&cache.stat);
+        let mut stat = synced_stat.write().unwrap();
+
+        match result {
+            Ok(fresh) => {
+                stat.last_updated = now;
+                stat.refresh_count += 1;
+                stat.expires_at = fresh.expire_at;
+
+                let token = fresh.token.clone();
+                cache.cached = Some(fresh);
+
+                Ok(token)
+            },
+            Err(e) => {
+                let error = format!("{:?}", e);
+                println!("failed to refresh token {}", error);
+
+                stat.last_error = Some(error.clone());
+                stat.last_error_time = now;
+
+                Err(error)
+            }
+        }
+    });
+
+    let refresh: Box<Future<Item = String, Error = String>> = Box::new(refresh);
+    let shared = refresh.shared();
+
+    cache.borrow_mut().pending = Some(shared.clone());
+    wait_token(shared)
+}
+
+fn wait_token(shared: SharedToken) -> Box<Future<Item = String, Error = CombinedError>> {
+    let future = shared
+        .map(|token| (*token).clone())
+        .map_err(|e| CombinedError::Other((*e).clone()));
+
+    Box::new(future)
+}
+
+//
+// OAuth2 client credentials grant (RFC 6749, section 4.4).
+//
+struct OAuth2Proxy {
+    handle: Handle,
+    credentials: OAuth2Credentials,
+    // Created on first use and reused for all refreshes.
+    client: Option<OrcaClient>,
+    cache: SharedTokenCache,
+}
+
+impl OAuth2Proxy {
+    pub fn new(handle: &Handle, credentials: &OAuth2Credentials, stat: Arc<SyncedTicketInfo>) -> OAuth2Proxy {
+        OAuth2Proxy {
+            handle: handle.clone(),
+            credentials: credentials.clone(),
+            client: None,
+            cache: TokenCache::new(stat),
+        }
+    }
+
+    fn client(&mut self) -> Result<OrcaClient, CombinedError> {
+        if self.client.is_none() {
+            self.client = Some(make_default_client(&self.handle)?);
+        }
+
+        Ok(self.client.clone().unwrap())
+    }
+
+    fn fetch_token(client: OrcaClient, credentials: &OAuth2Credentials) -> Box<Future<Item = CachedToken, Error = CombinedError>> {
+        let uri = match credentials.token_url.parse::<hyper::Uri>() {
+            Ok(uri) => uri,
+            Err(e) => return Box::new(futures::future::err(CombinedError::UriParseError(e))),
+        };
+
+        let mut body = format!("grant_type=client_credentials&client_id={}&client_secret={}",
+            urlencode(&credentials.client_id),
+            urlencode(&credentials.client_secret));
+
+        if let Some(ref scope) = credentials.scope {
+            body.push_str(&format!("&scope={}", urlencode(scope)));
+        }
+
+        let mut request = hyper::Request::new(hyper::Method::Post, uri);
+        request.headers_mut().set(ContentType::form_url_encoded());
+        request.headers_mut().set(ContentLength(body.len() as u64));
+        request.set_body(body);
+
+        let future = client.request(request)
+            .and_then(|res| {
+                let status = res.status();
+                res.body().concat2().map(move |raw| (status, raw))
+            })
+            .map_err(CombinedError::HyperError)
+            .and_then(|(status, raw)| {
+                if !status.is_success() {
+                    let message = format!("token endpoint responded with {}: {}",
+                        status, String::from_utf8_lossy(&raw));
+                    return Err(CombinedError::Other(message));
+                }
+
+                serde_json::from_slice::<OAuth2Token>(&raw)
+                    .map_err(CombinedError::SerdeError)
+            })
+            .map(|token| {
+                let now = time::get_time().sec;
+                let expires_in = token.expires_in.unwrap_or(DEFAULT_OAUTH2_EXPIRE_SEC);
+                let expire_at = now + expires_in - expires_in * REFRESH_AHEAD_PERCENT / 100;
+
+                CachedToken { token: token.access_token, expire_at }
+            });
+
+        Box::new(future)
+    }
+}
+
+impl SecureProxy for OAuth2Proxy {
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>> {
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(e) => return Box::new(futures::future::err(e)),
+        };
+
+        let credentials = &self.credentials;
+        let scheme = credentials.scheme.clone();
+
+        let header = cached_token(&self.cache, || Self::fetch_token(client, credentials))
+            .map(move |token| Some(format!("{} {}", scheme, token)));
+
+        Box::new(header)
+    }
+}
+
+
+//
+// Token is taken from stdout of external credential helper, which is killed
+// if it doesn't finish in time.
+//
+struct CommandProxy {
+    handle: Handle,
+    command: String,
+    args: Vec<String>,
+    scheme: String,
+    ttl_sec: i64,
+    cache: SharedTokenCache,
+}
+
+impl CommandProxy {
+    pub fn new(
+        handle: &Handle,
+        command: &str,
+        args: &[String],
+        scheme: &str,
+        ttl_sec: i64,
+        stat: Arc<SyncedTicketInfo>) -> CommandProxy
+    {
+        CommandProxy {
+            handle: handle.clone(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            scheme: scheme.to_string(),
+            ttl_sec,
+            cache: TokenCache::new(stat),
+        }
+    }
+
+    fn run(&self) -> Box<Future<Item = CachedToken, Error = CombinedError>> {
+        let timeout = match Timeout::new(Duration::from_secs(COMMAND_TIMEOUT_SEC), &self.handle) {
+            Ok(timeout) => timeout,
+            Err(e) => return Box::new(futures::future::err(CombinedError::IOError(e))),
+        };
+
+        let command = self.command.clone();
+        let ttl_sec = self.ttl_sec;
+
+        // Child is killed once output future is dropped, i.e. on timeout.
+        let output = Command::new(&self.command)
+            .args(&self.args)
+            .output_async(&self.handle)
+            .map_err(CombinedError::IOError)
+            .and_then(move |output| {
+                if !output.status.success() {
+                    let message = format!("credential helper {} exited with {}: {}",
+                        command, output.status, String::from_utf8_lossy(&output.stderr));
+                    return Err(CombinedError::Other(message));
+                }
+
+                let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if token.is_empty() {
+                    return Err(CombinedError::Other(format!("credential helper {} returned empty token", command)));
+                }
+
+                Ok(CachedToken { token, expire_at: time::get_time().sec + ttl_sec })
+            });
+
+        let command = self.command.clone();
+        let timeout = timeout
+            .map_err(CombinedError::IOError)
+            .and_then(move |_| Err::<CachedToken, _>(CombinedError::Other(
+                format!("credential helper {} hasn't finished in {} sec", command, COMMAND_TIMEOUT_SEC))));
+
+        let future = output.select(timeout)
+            .map(|(token, _)| token)
+            .map_err(|(e, _)| e);
+
+        Box::new(future)
+    }
+}
+
+impl SecureProxy for CommandProxy {
+    fn ticket_as_header(&mut self) -> Box<Future<Item = Option<String>, Error = CombinedError>> {
+        let scheme = self.scheme.clone();
+        let header = cached_token(&self.cache, || self.run())
+            .map(move |token| Some(format!("{} {}", scheme, token)));
+
+        Box::new(header)
+    }
+}
+
+
+fn urlencode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+
+/// Makes proxy of configured secure backend, tvm service is resolved
+/// through locator on first use.
+pub fn make_ticket_service(
+    locator: &Locator,
+    handle: &Handle,
+    config: &Config,
+    stat: Arc<SyncedTicketInfo>)
+    -> SharedProxy
 {
-    match config.secure {
-        Some(ref secure) => match &secure.md[..] {
-            "TVM" => Box::new(TvmProxy::new(service, &config.ticket_expire_sec, secure)),
-            _ => Box::new(PromiscuousProxy::new())
+    // Note: unknown `mod` values are rejected on config load.
+    let proxy: Box<SecureProxy> = match config.secure {
+        Some(ref secure) => match secure.backend {
+            SecureBackend::Tvm(ref credentials) =>
+                Box::new(TvmProxy::new(locator, handle, &config.ticket_expire_sec, &secure.get_mod(), credentials, stat)),
+            SecureBackend::StaticToken { ref path, ref scheme } =>
+                Box::new(StaticTokenProxy::new(path, scheme)),
+            SecureBackend::OAuth2(ref credentials) =>
+                Box::new(OAuth2Proxy::new(handle, credentials, stat)),
+            SecureBackend::Command { ref command, ref args, ref scheme, ttl_sec } =>
+                Box::new(CommandProxy::new(handle, command, args, scheme, ttl_sec, stat)),
         },
         None => Box::new(PromiscuousProxy::new())
-    }
+    };
+
+    Rc::new(RefCell::new(proxy))
 }
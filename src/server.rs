@@ -0,0 +1,188 @@
+//
+// Zorca web server listeners and connections handling.
+//
+use std::fs;
+use std::net::{self, SocketAddr};
+use std::os::unix;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream};
+use futures::future;
+
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_openssl::SslAcceptorExt;
+use tokio_uds::UnixListener;
+
+use hyper::server::Http;
+
+use access::Authenticator;
+use acceptor::SharedAcceptor;
+use cache::SharedResponseCache;
+use config::WebCors;
+use errors::CombinedError;
+use web::{Model, WebApi};
+
+
+pub const DEFAULT_LISTEN_ADDRESS: &str = "[::1]:3141";
+const UNIX_PREFIX: &str = "unix:";
+const DRAIN_TURN_MSEC: u64 = 100;
+
+// Peer address reported for unix socket connections, which have none.
+const UNIX_PEER_ADDRESS: &str = "0.0.0.0:0";
+
+
+// Listening sockets are bound once on startup, so bind failure is reported
+// before anything is served, and then shared by event loop restarts.
+pub enum Listener {
+    Tcp(net::TcpListener, SocketAddr),
+    Unix(unix::net::UnixListener, String),
+}
+
+pub struct WebContext {
+    pub model: Model,
+    pub auth: Option<Rc<Authenticator>>,
+    pub tls: Option<SharedAcceptor>,
+    pub static_path: String,
+    pub in_flight: Rc<Cell<usize>>,
+    pub cache: SharedResponseCache,
+    pub cors: Option<Rc<WebCors>>,
+}
+
+/// Binds address in form of `ip:port`, `[ip6]:port` or `unix:/path/to.sock`.
+pub fn bind(address: &str) -> Result<Listener, CombinedError> {
+    if address.starts_with(UNIX_PREFIX) {
+        let path = &address[UNIX_PREFIX.len()..];
+
+        // Stale socket left by previous run, anything else is never removed,
+        // so misconfigured path can't cost a file.
+        if Path::new(path).exists() {
+            if !fs::symlink_metadata(path)?.file_type().is_socket() {
+                let error = format!("{} exists and is not a unix socket, refusing to replace it", path);
+                return Err(CombinedError::Other(error));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = unix::net::UnixListener::bind(path)?;
+        return Ok(Listener::Unix(listener, path.to_string()));
+    }
+
+    let addr = address.parse::<SocketAddr>()
+        .map_err(|e| CombinedError::Other(format!("invalid listen address {}: {}", address, e)))?;
+
+    let listener = net::TcpListener::bind(&addr)?;
+    Ok(Listener::Tcp(listener, addr))
+}
+
+pub fn bind_all(addresses: &[String]) -> Result<Vec<Listener>, CombinedError> {
+    addresses.iter()
+        .map(|address| bind(address)
+            .map_err(|e| CombinedError::Other(format!("failed to bind {}: {:?}", address, e))))
+        .collect()
+}
+
+/// Makes future serving web api on all listeners until the first one fails.
+pub fn serve(handle: &Handle, listeners: &[Listener], ctx: Rc<WebContext>)
+    -> Result<Box<Future<Item=(), Error=CombinedError>>, CombinedError>
+{
+    let http = Http::new();
+    let mut servers: Vec<Box<Future<Item=(), Error=CombinedError>>> = Vec::with_capacity(listeners.len());
+
+    for listener in listeners {
+        let handle = handle.clone();
+        let http = http.clone();
+        let ctx = Rc::clone(&ctx);
+
+        match *listener {
+            Listener::Tcp(ref listener, ref addr) => {
+                println!("serving web api on {}", addr);
+
+                let listener = TcpListener::from_listener(listener.try_clone()?, addr, &handle)?;
+                let server = listener.incoming()
+                    .for_each(move |(sock, addr)| {
+                        serve_connection(&handle, &http, sock, addr, &ctx);
+                        Ok(())
+                    })
+                    .map_err(CombinedError::IOError);
+
+                servers.push(Box::new(server));
+            },
+            Listener::Unix(ref listener, ref path) => {
+                println!("serving web api on unix socket {}", path);
+
+                let addr = UNIX_PEER_ADDRESS.parse::<SocketAddr>().unwrap();
+                let listener = UnixListener::from_listener(listener.try_clone()?, &handle)?;
+                let server = listener.incoming()
+                    .for_each(move |(sock, _)| {
+                        serve_connection(&handle, &http, sock, addr, &ctx);
+                        Ok(())
+                    })
+                    .map_err(CombinedError::IOError);
+
+                servers.push(Box::new(server));
+            },
+        }
+    }
+
+    if servers.is_empty() {
+        return Err(CombinedError::Other("no listen addresses configured".to_string()));
+    }
+
+    let server = future::select_all(servers)
+        .map(|_| ())
+        .map_err(|(e, _, _)| e);
+
+    Ok(Box::new(server))
+}
+
+/// Keeps event loop running until in-flight requests are completed or
+/// timeout is expired, returns `true` if all requests were completed.
+pub fn drain(core: &mut Core, ctx: &WebContext, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    while ctx.in_flight.get() > 0 && Instant::now() < deadline {
+        core.turn(Some(Duration::from_millis(DRAIN_TURN_MSEC)));
+    }
+
+    ctx.in_flight.get() == 0
+}
+
+fn serve_connection<I>(handle: &Handle, http: &Http, io: I, addr: SocketAddr, ctx: &WebContext)
+where
+    I: AsyncRead + AsyncWrite + 'static
+{
+    let web = WebApi::new(
+        handle,
+        ctx.model.clone(),
+        &ctx.static_path,
+        ctx.auth.clone(),
+        Rc::clone(&ctx.in_flight),
+        Rc::clone(&ctx.cache),
+        ctx.cors.clone(),
+    );
+
+    match ctx.tls {
+        Some(ref acceptor) => {
+            let http = http.clone();
+            let conn_handle = handle.clone();
+
+            let accept = acceptor.borrow().accept_async(io)
+                .then(move |stream| {
+                    match stream {
+                        Ok(stream) => http.bind_connection(&conn_handle, stream, addr, web),
+                        Err(e) => println!("tls handshake with {} failed {:?}", addr, e),
+                    };
+                    Ok(())
+                });
+
+            handle.spawn(accept);
+        },
+        None => http.bind_connection(handle, io, addr, web)
+    };
+}
@@ -0,0 +1,441 @@
+//
+// Fake orca fleet serving orca web api on local ports, for demos and end to
+// end testing without real cluster.
+//
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::{Future, Stream, future};
+
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle, Timeout};
+use tokio_openssl::SslAcceptorExt;
+
+use hyper;
+use hyper::header::{ContentLength, ContentType};
+use hyper::server::{Http, Request, Response, Service};
+use hyper::StatusCode;
+
+use serde_json;
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use acceptor::make_acceptor;
+use config::WebTls;
+use errors::CombinedError;
+use orca::STARTED_STATE;
+
+
+pub const DEFAULT_LISTEN_HOST: &str = "::1";
+
+const DEFAULT_APPS: usize = 4;
+const DEFAULT_WORKERS: i64 = 2;
+const DEFAULT_ERROR_STATUS: u16 = 500;
+
+const SIMULATOR_VERSION: &str = "simulator";
+const SIMULATOR_PROFILE: &str = "default";
+
+
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub hosts: usize,
+    pub listen_host: String,
+    pub base_port: u16,
+    // Where to write hosts list of simulated fleet, in `file` cluster format.
+    pub hosts_file: Option<String>,
+    pub scenario: Scenario,
+    // If set, orcas are served over https, e.g. to check client tls setup.
+    pub tls: Option<WebTls>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    // Runtime workers count of app (all apps if not set) is off by `delta`.
+    Mismatch { app: Option<String>, delta: i64 },
+    // Each response is delayed.
+    Slow { delay_ms: u64 },
+    // Each request is failed with status.
+    Error { status: u16 },
+    // Orca is restarted, i.e. is unavailable while event lasts, then comes
+    // back with new uuid and uptime.
+    Restart,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    // Host index, all hosts if not set.
+    pub host: Option<usize>,
+    // Seconds since simulator start.
+    pub at_sec: u64,
+    pub until_sec: Option<u64>,
+    pub fault: Fault,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub apps: usize,
+    pub workers: i64,
+    pub events: Vec<Event>,
+}
+
+impl Event {
+    fn applies(&self, host: usize) -> bool {
+        self.host.map_or(true, |index| index == host)
+    }
+
+    fn is_active(&self, elapsed: u64) -> bool {
+        match self.until_sec {
+            Some(until) => elapsed >= self.at_sec && elapsed < until,
+            // Restart without downtime is instant.
+            None => elapsed >= self.at_sec && self.fault != Fault::Restart,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Scenario {
+        Scenario { apps: DEFAULT_APPS, workers: DEFAULT_WORKERS, events: Vec::new() }
+    }
+
+    /// Scenario file is yaml mapping with optional `apps` and `workers`
+    /// numbers and `events` list, e.g.
+    ///
+    ///     apps: 3
+    ///     events:
+    ///       - { host: 1, at_sec: 30, until_sec: 90, kind: mismatch, app: app0, delta: -1 }
+    ///       - { at_sec: 60, kind: slow, delay_ms: 3000 }
+    ///       - { host: 2, at_sec: 10, until_sec: 20, kind: restart }
+    ///       - { host: 3, at_sec: 0, kind: error, status: 503 }
+    pub fn from_file(path: &str) -> Result<Scenario, CombinedError> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        let docs = YamlLoader::load_from_str(&content)
+            .map_err(|e| CombinedError::ConfigError(format!("failed to parse scenario {}: {}", path, e)))?;
+
+        match docs.first() {
+            Some(doc) => Scenario::from_yaml(doc)
+                .map_err(|e| CombinedError::ConfigError(format!("scenario {}: {}", path, e))),
+            None => Ok(Scenario::new())
+        }
+    }
+
+    fn from_yaml(doc: &Yaml) -> Result<Scenario, String> {
+        let mut scenario = Scenario::new();
+
+        if let Some(apps) = doc["apps"].as_i64() {
+            scenario.apps = apps as usize;
+        }
+
+        if let Some(workers) = doc["workers"].as_i64() {
+            scenario.workers = workers;
+        }
+
+        match doc["events"] {
+            Yaml::Array(ref events) => for event in events {
+                scenario.events.push(parse_event(event)?);
+            },
+            Yaml::BadValue => {},
+            _ => return Err("`events` should be a list".to_string())
+        }
+
+        Ok(scenario)
+    }
+}
+
+fn parse_event(event: &Yaml) -> Result<Event, String> {
+    let fault = match event["kind"].as_str() {
+        Some("mismatch") => Fault::Mismatch {
+            app: event["app"].as_str().map(String::from),
+            delta: event["delta"].as_i64().unwrap_or(1),
+        },
+        Some("slow") => Fault::Slow {
+            delay_ms: event["delay_ms"].as_i64().ok_or("slow event `delay_ms` is required")? as u64,
+        },
+        Some("error") => Fault::Error {
+            status: event["status"].as_i64().map_or(DEFAULT_ERROR_STATUS, |status| status as u16),
+        },
+        Some("restart") => Fault::Restart,
+        Some(kind) => return Err(format!("unknown event kind {}", kind)),
+        None => return Err("event `kind` is required".to_string())
+    };
+
+    Ok(Event {
+        host: event["host"].as_i64().map(|host| host as usize),
+        at_sec: event["at_sec"].as_i64().unwrap_or(0) as u64,
+        until_sec: event["until_sec"].as_i64().map(|until| until as u64),
+        fault,
+    })
+}
+
+// Single simulated orca.
+struct FakeHost {
+    index: usize,
+    scenario: Rc<Scenario>,
+    started: Instant,
+    requests: Cell<u64>,
+}
+
+impl FakeHost {
+    fn app_name(index: usize) -> String {
+        format!("app{}", index)
+    }
+
+    fn active_faults(&self, elapsed: u64) -> Vec<&Fault> {
+        self.scenario.events.iter()
+            .filter(|event| event.applies(self.index) && event.is_active(elapsed))
+            .map(|event| &event.fault)
+            .collect()
+    }
+
+    // Time the last finished restart is done at, orca is "started" then.
+    fn last_restart(&self, elapsed: u64) -> u64 {
+        self.scenario.events.iter()
+            .filter(|event| event.applies(self.index) && event.fault == Fault::Restart)
+            .filter_map(|event| event.until_sec.or(Some(event.at_sec)))
+            .filter(|&done| done <= elapsed)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn restarts(&self, elapsed: u64) -> usize {
+        self.scenario.events.iter()
+            .filter(|event| event.applies(self.index) && event.fault == Fault::Restart)
+            .filter(|event| event.until_sec.unwrap_or(event.at_sec) <= elapsed)
+            .count()
+    }
+
+    fn workers(&self, app: usize) -> i64 {
+        self.scenario.workers + (self.index + app) as i64 % 3
+    }
+
+    fn runtime_workers(&self, app: usize, faults: &[&Fault]) -> i64 {
+        let name = FakeHost::app_name(app);
+
+        faults.iter().fold(self.workers(app), |workers, fault| match **fault {
+            Fault::Mismatch { app: None, delta } => workers + delta,
+            Fault::Mismatch { app: Some(ref target), delta } if *target == name => workers + delta,
+            _ => workers
+        })
+    }
+
+    fn respond(&self, path: &str, elapsed: u64, faults: &[&Fault]) -> Option<serde_json::Value> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let apps = 0..self.scenario.apps;
+
+        let body = match path {
+            "/info" => json!({
+                "uptime": elapsed - self.last_restart(elapsed),
+                "version": SIMULATOR_VERSION,
+                "uuid": format!("sim-{}-{}", self.index, self.restarts(elapsed)),
+            }),
+            "/v1/state" => {
+                let state: serde_json::Map<_, _> = apps
+                    .map(|app| (FakeHost::app_name(app), json!({
+                        "profile": SIMULATOR_PROFILE,
+                        "state": STARTED_STATE,
+                        "workers": self.workers(app),
+                        "state_version": 1,
+                        "about_state": null,
+                        "state_description": null,
+                        "time_stamp": now,
+                    })))
+                    .collect();
+                json!({ "state": state, "version": 1, "timestamp": now })
+            },
+            "/v1/incoming_state" => {
+                let state: serde_json::Map<_, _> = apps
+                    .map(|app| (FakeHost::app_name(app), json!({
+                        "profile": SIMULATOR_PROFILE,
+                        "workers": self.workers(app),
+                    })))
+                    .collect();
+                json!({ "state": state, "version": 1, "timestamp": now })
+            },
+            "/v1/distribution" => {
+                let distribution: serde_json::Map<_, _> = apps
+                    .map(|app| (FakeHost::app_name(app), json!(self.runtime_workers(app, faults))))
+                    .collect();
+                serde_json::Value::Object(distribution)
+            },
+            "/v1/metrics" => json!({
+                "simulator.requests": self.requests.get() as f64,
+                "simulator.uptime": (elapsed - self.last_restart(elapsed)) as f64,
+            }),
+            _ => return None
+        };
+
+        Some(body)
+    }
+}
+
+struct FakeOrca {
+    host: Rc<FakeHost>,
+    handle: Handle,
+}
+
+type BoxedResponseFuture = Box<Future<Item=Response, Error=hyper::Error>>;
+
+fn make_response(status: StatusCode, body: String) -> Response {
+    Response::new()
+        .with_status(status)
+        .with_header(ContentType::json())
+        .with_header(ContentLength(body.len() as u64))
+        .with_body(body)
+}
+
+impl Service for FakeOrca {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = BoxedResponseFuture;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let host = &self.host;
+        host.requests.set(host.requests.get() + 1);
+
+        let elapsed = host.started.elapsed().as_secs();
+        let faults = host.active_faults(elapsed);
+
+        let restarting = faults.iter().any(|fault| **fault == Fault::Restart);
+        let failed_with = faults.iter().filter_map(|fault| match **fault {
+            Fault::Error { status } => Some(status),
+            _ => None
+        }).next();
+        let delay_ms = faults.iter().filter_map(|fault| match **fault {
+            Fault::Slow { delay_ms } => Some(delay_ms),
+            _ => None
+        }).max();
+
+        let response = if restarting {
+            make_response(StatusCode::ServiceUnavailable, json!({"error": "restarting"}).to_string())
+        } else if let Some(status) = failed_with {
+            let status = StatusCode::try_from(status).unwrap_or(StatusCode::InternalServerError);
+            make_response(status, json!({"error": "simulated failure"}).to_string())
+        } else {
+            match host.respond(request.path(), elapsed, &faults) {
+                Some(body) => make_response(StatusCode::Ok, body.to_string()),
+                None => make_response(StatusCode::NotFound, json!({"error": "not found"}).to_string())
+            }
+        };
+
+        match delay_ms.map(|delay| Timeout::new(Duration::from_millis(delay), &self.handle)) {
+            Some(Ok(timeout)) => Box::new(timeout.then(move |_| Ok::<_, hyper::Error>(response))),
+            _ => Box::new(future::ok(response))
+        }
+    }
+}
+
+// In the same format as `file` cluster source expects.
+fn write_hosts_file(path: &str, fleet: &Fleet) -> Result<(), CombinedError> {
+    let mut file = File::create(path)?;
+
+    for (index, addr) in fleet.addrs.iter().enumerate() {
+        writeln!(file, "- hostname: {}", hostname(index))?;
+        writeln!(file, "  address: \"{}\"", addr.ip())?;
+        writeln!(file, "  port: {}", addr.port())?;
+    }
+
+    Ok(())
+}
+
+pub fn hostname(index: usize) -> String {
+    format!("sim-host{}.local", index)
+}
+
+pub struct Fleet {
+    // Resolved with the first orca server failure.
+    pub server: Box<Future<Item=(), Error=CombinedError>>,
+    // Orca addresses in order of host index.
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// Binds fake orca on `base_port + index` port for each host, or on any
+/// free port if base port is zero.
+pub fn serve(handle: &Handle, config: &SimulatorConfig) -> Result<Fleet, CombinedError> {
+    let http = Http::new();
+    let scenario = Rc::new(config.scenario.clone());
+    let started = Instant::now();
+    let acceptor = match config.tls {
+        Some(ref tls) => Some(Rc::new(make_acceptor(tls)?)),
+        None => None
+    };
+
+    let mut servers: Vec<Box<Future<Item=(), Error=CombinedError>>> = Vec::with_capacity(config.hosts);
+    let mut addrs = Vec::with_capacity(config.hosts);
+
+    for index in 0..config.hosts {
+        let port = match config.base_port {
+            0 => 0,
+            base_port => base_port as usize + index
+        };
+        let addr = format!("[{}]:{}", config.listen_host, port).parse::<SocketAddr>()
+            .or_else(|_| format!("{}:{}", config.listen_host, port).parse::<SocketAddr>())
+            .map_err(|e| CombinedError::ConfigError(format!("invalid listen host {}: {}", config.listen_host, e)))?;
+
+        let listener = TcpListener::bind(&addr, handle)?;
+        let addr = listener.local_addr()?;
+        println!("simulating orca {} on {}", hostname(index), addr);
+
+        let host = Rc::new(FakeHost { index, scenario: Rc::clone(&scenario), started, requests: Cell::new(0) });
+        let handle = handle.clone();
+        let http = http.clone();
+        let acceptor = acceptor.clone();
+
+        let server = listener.incoming()
+            .for_each(move |(sock, peer)| {
+                let service = FakeOrca { host: Rc::clone(&host), handle: handle.clone() };
+
+                match acceptor {
+                    Some(ref acceptor) => {
+                        let http = http.clone();
+                        let conn_handle = handle.clone();
+
+                        let accept = acceptor.accept_async(sock)
+                            .then(move |stream| {
+                                match stream {
+                                    Ok(stream) => http.bind_connection(&conn_handle, stream, peer, service),
+                                    Err(e) => println!("tls handshake with {} failed {:?}", peer, e),
+                                };
+                                Ok(())
+                            });
+
+                        handle.spawn(accept);
+                    },
+                    None => http.bind_connection(&handle, sock, peer, service)
+                };
+                Ok(())
+            })
+            .map_err(CombinedError::IOError);
+
+        servers.push(Box::new(server));
+        addrs.push(addr);
+    }
+
+    if servers.is_empty() {
+        return Err(CombinedError::ConfigError("nothing to simulate, hosts count is zero".to_string()));
+    }
+
+    let server = future::select_all(servers)
+        .map(|_| ())
+        .map_err(|(e, _, _)| e);
+
+    Ok(Fleet { server: Box::new(server), addrs })
+}
+
+/// Serves fake orca fleet until failure.
+pub fn run(config: SimulatorConfig) -> Result<(), CombinedError> {
+    let mut core = Core::new()?;
+    let fleet = serve(&core.handle(), &config)?;
+
+    if let Some(ref path) = config.hosts_file {
+        write_hosts_file(path, &fleet)?;
+        println!("hosts list is written to {}", path);
+    }
+
+    core.run(fleet.server)
+}
@@ -0,0 +1,146 @@
+//
+// Immutable snapshots of monitored state. Working state keeps each part
+// behind `Arc`, writers copy it on change, so publishing only shares
+// pointers and readers never touch working locks.
+//
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+
+use breaker::Breakers;
+use changes::Change;
+use engine::{Cluster, ClusterView, ClusterViews};
+use health::now_secs;
+use orca::{Apps, OrcasPod, SyncedApps};
+
+
+#[derive(Debug, Clone)]
+pub struct ClusterSnapshot {
+    // Published by subscription along with changes found.
+    pub cluster: Arc<Cluster>,
+    pub changes: Arc<VecDeque<Change>>,
+    // Published by gather once per cycle.
+    pub orcas: Arc<OrcasPod>,
+    pub apps: Arc<Apps>,
+    pub breakers: Arc<Breakers>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    // Time snapshot was published at, zero if nothing is published yet.
+    pub timestamp: u64,
+    // Incremented on each publish, so readers can tell snapshots apart.
+    pub generation: u64,
+    // mapping: cluster name -> cluster state
+    pub clusters: BTreeMap<String, ClusterSnapshot>,
+    // Apps of all clusters merged.
+    pub apps: Arc<Apps>,
+}
+
+impl Snapshot {
+    pub fn new() -> Snapshot {
+        Snapshot { timestamp: 0, generation: 0, clusters: BTreeMap::new(), apps: Arc::new(Apps::new()) }
+    }
+}
+
+// Lock is held only to clone or swap pointer, never while serializing.
+pub type SyncedSnapshot = RwLock<Arc<Snapshot>>;
+
+// Nodes and changes are locked together, so changelog always matches nodes.
+fn nodes_of(view: &ClusterView) -> (Arc<Cluster>, Arc<VecDeque<Change>>) {
+    let cluster = view.cluster.read().unwrap();
+    let changelog = view.changes.read().unwrap();
+
+    (Arc::clone(&cluster), Arc::clone(changelog.changes()))
+}
+
+fn gathered_of(view: &ClusterView) -> (Arc<OrcasPod>, Arc<Apps>, Arc<Breakers>) {
+    let orcas = view.orcas.read().unwrap();
+    let apps = view.apps.read().unwrap();
+    let breakers = view.breakers.read().unwrap();
+
+    (Arc::clone(&orcas), Arc::clone(&apps), Arc::clone(&breakers))
+}
+
+/// Builds snapshots from cluster views, shared by all updaters.
+#[derive(Clone)]
+pub struct Publisher {
+    views: Arc<ClusterViews>,
+    apps: Arc<SyncedApps>,
+    current: Arc<SyncedSnapshot>,
+    // Serializes publishers, so one never drops part published by another.
+    publishing: Arc<Mutex<()>>,
+}
+
+impl Publisher {
+    // Initial snapshot is published right away, so every cluster is listed.
+    pub fn new(views: Arc<ClusterViews>, apps: Arc<SyncedApps>) -> Publisher {
+        let publisher = Publisher {
+            views,
+            apps,
+            current: Arc::new(SyncedSnapshot::new(Arc::new(Snapshot::new()))),
+            publishing: Arc::new(Mutex::new(())),
+        };
+
+        publisher.update(|snapshot, views, merged| {
+            snapshot.clusters = views.iter()
+                .map(|(name, view)| {
+                    let (cluster, changes) = nodes_of(view);
+                    let (orcas, apps, breakers) = gathered_of(view);
+                    (name.clone(), ClusterSnapshot { cluster, changes, orcas, apps, breakers })
+                })
+                .collect();
+            snapshot.apps = Arc::clone(&merged.read().unwrap());
+        });
+
+        publisher
+    }
+
+    pub fn load(&self) -> Arc<Snapshot> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Shares nodes and changes of cluster, should be called once
+    /// subscription has changed them.
+    pub fn publish_nodes(&self, name: &str) {
+        self.update(|snapshot, views, _| {
+            if let (Some(view), Some(cluster)) = (views.get(name), snapshot.clusters.get_mut(name)) {
+                let (nodes, changes) = nodes_of(view);
+                cluster.cluster = nodes;
+                cluster.changes = changes;
+            }
+        });
+    }
+
+    /// Shares gathered state of all clusters, should be called once per
+    /// gather cycle.
+    pub fn publish_gathered(&self) {
+        self.update(|snapshot, views, merged| {
+            for (name, cluster) in snapshot.clusters.iter_mut() {
+                if let Some(view) = views.get(name) {
+                    let (orcas, apps, breakers) = gathered_of(view);
+                    cluster.orcas = orcas;
+                    cluster.apps = apps;
+                    cluster.breakers = breakers;
+                }
+            }
+            snapshot.apps = Arc::clone(&merged.read().unwrap());
+        });
+    }
+
+    // Current snapshot is cloned shallowly, parts left untouched by update
+    // are shared with it.
+    fn update<F>(&self, update: F)
+    where
+        F: FnOnce(&mut Snapshot, &ClusterViews, &SyncedApps)
+    {
+        let _publishing = self.publishing.lock().unwrap();
+
+        let mut snapshot = Snapshot::clone(&self.load());
+        update(&mut snapshot, &self.views, &self.apps);
+
+        snapshot.timestamp = now_secs();
+        snapshot.generation += 1;
+
+        *self.current.write().unwrap() = Arc::new(snapshot);
+    }
+}
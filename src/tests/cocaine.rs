@@ -0,0 +1,263 @@
+//
+// Fake cocaine node speaking just enough of the protocol for zorca: locator
+// `resolve` and unicorn `children_subscribe` and `get`.
+//
+// Frames are msgpack arrays of `[span, type, args]`, optional headers are
+// ignored. Unicorn calls are told apart by path rather than by method id:
+// kids path is subscribed to, paths under it are read.
+//
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Cursor};
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+use bytes::BytesMut;
+
+use futures::{Future, Sink, Stream};
+use futures::sync::{mpsc, oneshot};
+
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle};
+use tokio_io::AsyncRead;
+use tokio_io::codec::{Decoder, Encoder};
+
+use rmpv::{self, Value};
+use serde_json::{self, Value as Json};
+
+use resources::NodeInfo;
+
+
+// Type of value reply, the only one fake ever sends.
+const VALUE_TYPE: u64 = 0;
+
+// Version locator reports for resolved service.
+const SERVICE_VERSION: u64 = 1;
+
+
+struct MsgpackCodec;
+
+impl Decoder for MsgpackCodec {
+    type Item = Value;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Value>> {
+        let (frame, len) = {
+            let mut cursor = Cursor::new(&src[..]);
+            match rmpv::decode::read_value(&mut cursor) {
+                Ok(frame) => (frame, cursor.position() as usize),
+                // Frame isn't received completely yet.
+                Err(_) => return Ok(None),
+            }
+        };
+
+        src.split_to(len);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder for MsgpackCodec {
+    type Item = Value;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Value, dst: &mut BytesMut) -> io::Result<()> {
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, &frame)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("failed to encode frame: {:?}", e)))?;
+
+        dst.extend_from_slice(&buf);
+        Ok(())
+    }
+}
+
+struct State {
+    kids: String,
+    version: i64,
+    // mapping: uuid -> (node info, node version)
+    nodes: BTreeMap<String, (NodeInfo, i64)>,
+    // Span and replies queue of each open subscription.
+    subscribers: Vec<(u64, mpsc::UnboundedSender<Value>)>,
+    subscriptions: usize,
+    // Dropped to break connections.
+    connections: Vec<oneshot::Sender<()>>,
+}
+
+impl State {
+    fn children(&self) -> Vec<Value> {
+        let uuids = self.nodes.keys()
+            .map(|uuid| Value::from(uuid.as_str()))
+            .collect();
+
+        vec![ Value::from(self.version), Value::Array(uuids) ]
+    }
+}
+
+/// Locator resolving any service to fake unicorn, which stores nodes under
+/// `kids` path.
+pub struct FakeCocaine {
+    pub locator: SocketAddr,
+    state: Rc<RefCell<State>>,
+}
+
+impl FakeCocaine {
+    pub fn new(core: &Core, kids: &str) -> FakeCocaine {
+        let state = Rc::new(RefCell::new(State {
+            kids: kids.to_string(),
+            version: 0,
+            nodes: BTreeMap::new(),
+            subscribers: Vec::new(),
+            subscriptions: 0,
+            connections: Vec::new(),
+        }));
+
+        let handle = core.handle();
+        let unicorn = serve(&handle, &state, on_unicorn_request);
+
+        let locator = serve(&handle, &state, move |_, span, _, replies| {
+            let endpoint = Value::Array(vec![ Value::from(unicorn.ip().to_string()), Value::from(unicorn.port()) ]);
+            let resolved = vec![
+                Value::Array(vec![ endpoint ]),
+                Value::from(SERVICE_VERSION),
+                Value::Map(Vec::new()),
+            ];
+
+            let _ = replies.unbounded_send(reply(span, resolved));
+        });
+
+        FakeCocaine { locator, state }
+    }
+
+    pub fn put(&self, uuid: &str, info: NodeInfo, version: i64) {
+        self.state.borrow_mut().nodes.insert(uuid.to_string(), (info, version));
+    }
+
+    pub fn remove(&self, uuid: &str) {
+        self.state.borrow_mut().nodes.remove(uuid);
+    }
+
+    /// Bumps kids version and sends current children to subscribers.
+    pub fn publish(&self) {
+        let mut state = self.state.borrow_mut();
+        state.version += 1;
+
+        let children = state.children();
+        state.subscribers.retain(|&(span, ref replies)|
+            replies.unbounded_send(reply(span, children.clone())).is_ok());
+    }
+
+    /// Closes all connections accepted so far, both locator and unicorn.
+    pub fn break_connections(&self) {
+        let mut state = self.state.borrow_mut();
+        state.subscribers.clear();
+        state.connections.clear();
+    }
+
+    /// Count of `children_subscribe` calls made so far.
+    pub fn subscriptions(&self) -> usize {
+        self.state.borrow().subscriptions
+    }
+}
+
+fn on_unicorn_request(state: &Rc<RefCell<State>>, span: u64, args: &[Value], replies: &mpsc::UnboundedSender<Value>) {
+    let path = match args.first().and_then(|path| path.as_str()) {
+        Some(path) => path.to_string(),
+        None => return,
+    };
+
+    let mut state = state.borrow_mut();
+    let prefix = format!("{}/", state.kids);
+
+    if path == state.kids {
+        state.subscriptions += 1;
+        let _ = replies.unbounded_send(reply(span, state.children()));
+        state.subscribers.push((span, replies.clone()));
+    } else if path.starts_with(&prefix) {
+        // Missing node is read as empty one, as real unicorn does.
+        let (data, version) = match state.nodes.get(&path[prefix.len()..]) {
+            Some(&(ref info, version)) => (to_msgpack(serde_json::to_value(info).unwrap()), version),
+            None => (Value::Nil, 0),
+        };
+
+        let _ = replies.unbounded_send(reply(span, vec![ data, Value::from(version) ]));
+    }
+}
+
+/// Serves connections with `on_request` called once per new span.
+fn serve<F>(handle: &Handle, state: &Rc<RefCell<State>>, on_request: F) -> SocketAddr
+    where F: Fn(&Rc<RefCell<State>>, u64, &[Value], &mpsc::UnboundedSender<Value>) + 'static
+{
+    let addr = "127.0.0.1:0".parse().unwrap();
+    let listener = TcpListener::bind(&addr, handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let on_request = Rc::new(on_request);
+    let state = Rc::clone(state);
+    let spawner = handle.clone();
+
+    let server = listener.incoming().for_each(move |(socket, _)| {
+        let (sink, frames) = socket.framed(MsgpackCodec).split();
+        let (replies, outgoing) = mpsc::unbounded();
+
+        let (connection, broken) = oneshot::channel();
+        state.borrow_mut().connections.push(connection);
+
+        let on_request = Rc::clone(&on_request);
+        let state = Rc::clone(&state);
+        let mut spans = HashSet::new();
+
+        // Frames for already open channels, e.g. subscription close, aren't requests.
+        let reader = frames.for_each(move |frame| {
+            if let Some((span, args)) = parse(&frame) {
+                if spans.insert(span) {
+                    on_request(&state, span, &args, &replies);
+                }
+            }
+            Ok(())
+        });
+
+        let outgoing = outgoing.map_err(|_| io::Error::new(io::ErrorKind::Other, "replies queue has failed"));
+        let writer = sink.send_all(outgoing).map(|_| ());
+
+        let serving = reader.select(writer)
+            .map(|_| ())
+            .map_err(|_| ())
+            .select(broken.then(|_| Ok::<_, ()>(())))
+            .then(|_| Ok(()));
+
+        spawner.spawn(serving);
+        Ok(())
+    });
+
+    handle.spawn(server.map_err(|e| println!("fake cocaine has failed {:?}", e)));
+
+    addr
+}
+
+fn parse(frame: &Value) -> Option<(u64, Vec<Value>)> {
+    let frame = frame.as_array()?;
+    let span = frame.get(0)?.as_u64()?;
+    let args = frame.get(2)?.as_array()?;
+
+    Some((span, args.clone()))
+}
+
+fn reply(span: u64, args: Vec<Value>) -> Value {
+    Value::Array(vec![ Value::from(span), Value::from(VALUE_TYPE), Value::Array(args) ])
+}
+
+fn to_msgpack(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::from(b),
+        Json::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => Value::from(n),
+            (None, Some(n)) => Value::from(n),
+            (None, None) => Value::from(n.as_f64().unwrap_or_default()),
+        },
+        Json::String(s) => Value::from(s),
+        Json::Array(items) => Value::Array(items.into_iter().map(to_msgpack).collect()),
+        Json::Object(fields) => Value::Map(fields.into_iter()
+            .map(|(k, v)| (Value::from(k), to_msgpack(v)))
+            .collect()),
+    }
+}
@@ -0,0 +1,158 @@
+use tokio_core::reactor::Core;
+
+use breaker::{BreakerConfig, BreakerState};
+use config::{OrcaWeb, Tls};
+use engine::{ClusterView, replace_nodes};
+use health::now_secs;
+use orca::{Apps, AppsTrait};
+use simulator::{self, Event, Fault, Scenario};
+
+use super::{
+    CLUSTER,
+    LOCAL_IPV4,
+    fake_fleet,
+    fake_fleet_at,
+    fake_https_fleet,
+    fleet_view,
+    gather_once,
+    gather_once_with,
+    make_node,
+    self_signed_localhost,
+};
+
+
+#[test]
+fn gathers_all_hosts() {
+    let mut core = Core::new().unwrap();
+    let addrs = fake_fleet(&core, 2, Scenario::new());
+    let view = fleet_view(&addrs);
+
+    assert_eq!(gather_once(&mut core, &view, BreakerConfig::new()), 2);
+
+    let orcas = view.orcas.read().unwrap();
+    assert_eq!(orcas.len(), 2);
+
+    for index in 0..2 {
+        let record = &orcas[&simulator::hostname(index)];
+        assert_eq!(record.orca.info.version, "simulator");
+        assert!(record.orca.mismatched.is_empty());
+    }
+
+    let mut apps = Apps::new();
+    apps.update(&orcas);
+
+    assert_eq!(apps.len(), Scenario::new().apps);
+    assert!(apps.values().all(|stat| stat.hosts.len() == 2));
+}
+
+#[test]
+fn gathers_ipv4_hosts() {
+    let mut core = Core::new().unwrap();
+    let addrs = fake_fleet_at(&core, LOCAL_IPV4, 2, Scenario::new());
+    let view = fleet_view(&addrs);
+
+    gather_once(&mut core, &view, BreakerConfig::new());
+
+    let orcas = view.orcas.read().unwrap();
+    assert_eq!(orcas.len(), 2);
+    assert_eq!(orcas[&simulator::hostname(0)].orca.endpoints[0].host_str(), LOCAL_IPV4);
+}
+
+#[test]
+fn requests_host_without_address_by_name() {
+    let mut core = Core::new().unwrap();
+    let addrs = fake_fleet_at(&core, LOCAL_IPV4, 1, Scenario::new());
+
+    let mut node = make_node("localhost", &addrs[0]);
+    node.endpoints.clear();
+
+    let view = ClusterView::new();
+    replace_nodes(&view, CLUSTER, vec![("localhost".to_string(), node)], now_secs());
+
+    gather_once(&mut core, &view, BreakerConfig::new());
+
+    let orcas = view.orcas.read().unwrap();
+    assert!(orcas["localhost"].orca.endpoints.is_empty());
+    assert_eq!(orcas["localhost"].orca.info.version, "simulator");
+}
+
+#[test]
+fn gathers_https_hosts_by_name() {
+    let mut core = Core::new().unwrap();
+    let tls = self_signed_localhost("gather");
+    let addrs = fake_https_fleet(&core, tls.clone(), 1);
+
+    // Address is known, but certificate is issued for the name.
+    let view = ClusterView::new();
+    let node = make_node("localhost", &addrs[0]);
+    replace_nodes(&view, CLUSTER, vec![("localhost".to_string(), node)], now_secs());
+
+    let web = OrcaWeb {
+        scheme: "https".to_string(),
+        tls: Some(Tls {
+            ca_bundle: Some(tls.cert.clone()),
+            client_identity: None,
+            client_identity_password: String::new(),
+            verify_hostname: true,
+        }),
+        ..OrcaWeb::new()
+    };
+    gather_once_with(&mut core, &view, BreakerConfig::new(), web);
+
+    let orcas = view.orcas.read().unwrap();
+    assert_eq!(orcas["localhost"].orca.info.version, "simulator");
+}
+
+#[test]
+fn reports_mismatched_apps() {
+    let mut scenario = Scenario::new();
+    scenario.events.push(Event {
+        host: Some(1),
+        at_sec: 0,
+        until_sec: None,
+        fault: Fault::Mismatch { app: Some("app1".to_string()), delta: -1 },
+    });
+
+    let mut core = Core::new().unwrap();
+    let addrs = fake_fleet(&core, 2, scenario);
+    let view = fleet_view(&addrs);
+
+    gather_once(&mut core, &view, BreakerConfig::new());
+
+    let orcas = view.orcas.read().unwrap();
+    assert!(orcas[&simulator::hostname(0)].orca.mismatched.is_empty());
+
+    let mismatched = &orcas[&simulator::hostname(1)].orca.mismatched;
+    assert_eq!(mismatched.len(), 1);
+
+    let app = &mismatched["app1"];
+    assert!(app.mismatch_runtime);
+    assert!(!app.mismatch_inout);
+    assert_eq!(app.runtime, app.input - 1);
+}
+
+#[test]
+fn failed_host_opens_breaker() {
+    let mut scenario = Scenario::new();
+    scenario.events.push(Event {
+        host: Some(1),
+        at_sec: 0,
+        until_sec: None,
+        fault: Fault::Error { status: 503 },
+    });
+
+    let mut core = Core::new().unwrap();
+    let addrs = fake_fleet(&core, 2, scenario);
+    let view = fleet_view(&addrs);
+
+    let breaker_config = BreakerConfig { failure_threshold: 1, ..BreakerConfig::new() };
+    assert_eq!(gather_once(&mut core, &view, breaker_config), 1);
+
+    let orcas = view.orcas.read().unwrap();
+    assert!(orcas.contains_key(&simulator::hostname(0)));
+    assert!(!orcas.contains_key(&simulator::hostname(1)));
+
+    let breakers = view.breakers.read().unwrap();
+    assert_eq!(breakers[&simulator::hostname(1)].state, BreakerState::Open);
+    assert!(breakers[&simulator::hostname(1)].last_error.is_some());
+}
@@ -0,0 +1,80 @@
+use cocaine::hpack::RawHeader;
+
+use tokio_core::reactor::Core;
+
+use config::Locator;
+use errors::CombinedError;
+use locator::{make_resolved_service, probe};
+use resources::NodeInfo;
+use unicorn::get_node;
+
+use super::{closed_port, make_node};
+use super::cocaine::FakeCocaine;
+
+
+fn make_locator(endpoints: Vec<String>) -> Locator {
+    Locator {
+        endpoints,
+        unicorn_service: "unicorn".to_string(),
+        tvm_service: "tvm".to_string(),
+    }
+}
+
+#[test]
+fn probes_first_reachable_endpoint() {
+    let mut core = Core::new().unwrap();
+    let alive = FakeCocaine::new(&core, "/kids").locator;
+    let locator = make_locator(vec![ closed_port().to_string(), alive.to_string() ]);
+
+    let handle = core.handle();
+    let addr = core.run(probe(&handle, &locator)).expect("locator should be reachable");
+    assert_eq!(addr, alive);
+}
+
+#[test]
+fn reports_unreachable_locator() {
+    let mut core = Core::new().unwrap();
+    let locator = make_locator(vec![ closed_port().to_string() ]);
+
+    let handle = core.handle();
+    match core.run(probe(&handle, &locator)) {
+        Err(CombinedError::LocatorError(_)) => {},
+        other => panic!("locator error expected, got {:?}", other),
+    }
+}
+
+#[test]
+fn reports_unresolved_locator() {
+    let mut core = Core::new().unwrap();
+    // Port can't be parsed, so it fails without any DNS lookup.
+    let locator = make_locator(vec![ "127.0.0.1:locator".to_string() ]);
+
+    let handle = core.handle();
+    match core.run(probe(&handle, &locator)) {
+        Err(CombinedError::LocatorError(_)) => {},
+        other => panic!("locator error expected, got {:?}", other),
+    }
+
+    // No silent fallback to default locator.
+    assert!(make_resolved_service("unicorn", &handle, &[]).is_err());
+}
+
+#[test]
+fn resolves_service_through_locator() {
+    let mut core = Core::new().unwrap();
+    let cocaine = FakeCocaine::new(&core, "/kids");
+    let info = make_node("host-a", &closed_port());
+    cocaine.put("a", info.clone(), 7);
+
+    let handle = core.handle();
+    let unicorn = make_resolved_service("unicorn", &handle, &[ cocaine.locator ]).unwrap();
+
+    let (data, version) = core.run(get_node::<_, NodeInfo>(unicorn.clone(), None::<Vec<RawHeader>>, "/kids/a"))
+        .expect("node should be read");
+    assert_eq!(data.map(|node| node.hostname), Some(info.hostname));
+    assert_eq!(version, 7);
+
+    let (data, _) = core.run(get_node::<_, NodeInfo>(unicorn, None::<Vec<RawHeader>>, "/kids/missing"))
+        .expect("missing node should be read as empty");
+    assert!(data.is_none());
+}
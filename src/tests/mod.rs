@@ -0,0 +1,265 @@
+//
+// Integration test harness: fake orca fleet served by simulator, fake
+// cocaine locator and unicorn, and helpers to drive scripted scenarios.
+//
+// Note: `FakeUnicorn` feeds events and fetches to `process_nodes` directly,
+//       `FakeCocaine` serves them over cocaine protocol to `subscription`.
+//
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::net::{self, SocketAddr};
+use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, future, stream};
+
+use tokio_core::reactor::{Core, Timeout};
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509Builder, X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+
+use breaker::BreakerConfig;
+use config::{OrcaWeb, WebTls};
+use connector;
+use engine::{ClusterView, FetchFuture, NodesEvent, gather, replace_nodes};
+use errors::CombinedError;
+use health::now_secs;
+use resources::{Endpoint, NodeInfo, Resources};
+use simulator::{self, Scenario, SimulatorConfig};
+
+mod cocaine;
+mod gather;
+mod locator;
+mod subscription;
+mod web;
+
+
+pub const CLUSTER: &str = "test";
+const LOCAL_IPV6: &str = "::1";
+pub const LOCAL_IPV4: &str = "127.0.0.1";
+
+const RUN_UNTIL_SEC: u64 = 10;
+const RUN_UNTIL_TURN_MSEC: u64 = 10;
+
+
+/// Serves fake orcas on free local ports for the lifetime of core.
+pub fn fake_fleet(core: &Core, hosts: usize, scenario: Scenario) -> Vec<SocketAddr> {
+    fake_fleet_at(core, LOCAL_IPV6, hosts, scenario)
+}
+
+pub fn fake_fleet_at(core: &Core, listen_host: &str, hosts: usize, scenario: Scenario) -> Vec<SocketAddr> {
+    serve_fleet(core, SimulatorConfig {
+        hosts,
+        listen_host: listen_host.to_string(),
+        base_port: 0,
+        hosts_file: None,
+        scenario,
+        tls: None,
+    })
+}
+
+/// The same as fake fleet, but served over https with given certificate.
+pub fn fake_https_fleet(core: &Core, tls: WebTls, hosts: usize) -> Vec<SocketAddr> {
+    serve_fleet(core, SimulatorConfig {
+        hosts,
+        listen_host: LOCAL_IPV4.to_string(),
+        base_port: 0,
+        hosts_file: None,
+        scenario: Scenario::new(),
+        tls: Some(tls),
+    })
+}
+
+fn serve_fleet(core: &Core, config: SimulatorConfig) -> Vec<SocketAddr> {
+    let fleet = simulator::serve(&core.handle(), &config).expect("failed to start fake orca fleet");
+    core.handle().spawn(fleet.server.map_err(|e| println!("fake orca fleet has failed {:?}", e)));
+
+    fleet.addrs
+}
+
+/// Address nobody listens on.
+pub fn closed_port() -> SocketAddr {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+pub fn make_node(hostname: &str, addr: &SocketAddr) -> NodeInfo {
+    NodeInfo {
+        hostname: hostname.to_string(),
+        resources: Resources { cpu: 100, mem: 1024 },
+        endpoints: vec![ Endpoint(addr.ip().to_string(), 0) ],
+        cluster: String::new(),
+        unicorn_version: None,
+        stale_since: None,
+        orca_port: Some(addr.port()),
+    }
+}
+
+/// View with fleet hosts, named the same way simulator names them.
+pub fn fleet_view(addrs: &[SocketAddr]) -> ClusterView {
+    let view = ClusterView::new();
+    let nodes = addrs.iter().enumerate()
+        .map(|(index, addr)| (simulator::hostname(index), make_node(&simulator::hostname(index), addr)))
+        .collect();
+
+    replace_nodes(&view, CLUSTER, nodes, now_secs());
+    view
+}
+
+/// Runs single gather cycle over view, returns number of hosts gathered.
+pub fn gather_once(core: &mut Core, view: &ClusterView, breaker_config: BreakerConfig) -> usize {
+    gather_once_with(core, view, breaker_config, OrcaWeb::new())
+}
+
+pub fn gather_once_with(core: &mut Core, view: &ClusterView, breaker_config: BreakerConfig, web: OrcaWeb) -> usize {
+    let client = connector::make_client(&core.handle(), &web).unwrap();
+
+    let work = gather(
+        &client,
+        Arc::clone(&view.cluster),
+        Arc::clone(&view.orcas),
+        Arc::clone(&view.breakers),
+        breaker_config,
+        web.clone(),
+        None,
+    );
+
+    core.run(work).expect("gather has failed")
+}
+
+/// Self-signed certificate for `localhost`, written to temporary files, so
+/// it serves both as server certificate and as client CA bundle.
+pub fn self_signed_localhost(name: &str) -> WebTls {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", "localhost").unwrap();
+    let subject = subject.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(san).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = builder.build();
+
+    WebTls {
+        cert: temp_file(&format!("{}.crt", name), &cert.to_pem().unwrap()),
+        key: temp_file(&format!("{}.key", name), &key.private_key_to_pem_pkcs8().unwrap()),
+        client_ca: None,
+    }
+}
+
+/// Writes file into temp dir, unique per test process, returns its path.
+pub fn temp_file(name: &str, content: &[u8]) -> String {
+    let path = env::temp_dir().join(format!("zorca-test-{}-{}", process::id(), name));
+    File::create(&path).and_then(|mut file| file.write_all(content)).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+pub fn sleep(core: &mut Core, duration: Duration) {
+    let timeout = Timeout::new(duration, &core.handle()).unwrap();
+    core.run(timeout).unwrap();
+}
+
+/// Turns core till condition holds, fails test if it doesn't in time.
+pub fn run_until<F: FnMut() -> bool>(core: &mut Core, what: &str, mut condition: F) {
+    let deadline = Instant::now() + Duration::from_secs(RUN_UNTIL_SEC);
+
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        core.turn(Some(Duration::from_millis(RUN_UNTIL_TURN_MSEC)));
+    }
+}
+
+// Stand-in of unicorn nodes storage, shared by scenario script and fetcher.
+#[derive(Clone)]
+pub struct FakeUnicorn {
+    // mapping: uuid -> (node info, node version)
+    nodes: Rc<RefCell<HashMap<String, (NodeInfo, i64)>>>,
+    failing: Rc<RefCell<HashSet<String>>>,
+}
+
+/// Scenario step, either unicorn change or event to be processed.
+pub enum Step {
+    Put(&'static str, NodeInfo, i64),
+    Remove(&'static str),
+    Fail(&'static str),
+    Recover(&'static str),
+    Event(NodesEvent),
+}
+
+impl FakeUnicorn {
+    pub fn new() -> FakeUnicorn {
+        FakeUnicorn {
+            nodes: Rc::new(RefCell::new(HashMap::new())),
+            failing: Rc::new(RefCell::new(HashSet::new())),
+        }
+    }
+
+    // Missing node is fetched as empty one, as real unicorn does.
+    pub fn fetcher(&self) -> impl Fn(Vec<String>) -> FetchFuture {
+        let unicorn = self.clone();
+
+        move |uuids: Vec<String>| -> FetchFuture {
+            let nodes = unicorn.nodes.borrow();
+            let failing = unicorn.failing.borrow();
+
+            let fetched = uuids.into_iter()
+                .map(|uuid| match (failing.contains(&uuid), nodes.get(&uuid)) {
+                    (true, _) => None,
+                    (false, Some(&(ref info, version))) => Some((uuid, Some(info.clone()), version)),
+                    (false, None) => Some((uuid, None, 0)),
+                })
+                .collect();
+
+            Box::new(future::ok(fetched))
+        }
+    }
+
+    /// Turns script into events stream, unicorn changes are applied as soon
+    /// as previous event is processed.
+    pub fn script(&self, steps: Vec<Step>) -> impl Stream<Item=NodesEvent, Error=CombinedError> {
+        let unicorn = self.clone();
+
+        stream::iter_ok(steps).filter_map(move |step| match step {
+            Step::Put(uuid, info, version) => {
+                unicorn.nodes.borrow_mut().insert(uuid.to_string(), (info, version));
+                None
+            },
+            Step::Remove(uuid) => {
+                unicorn.nodes.borrow_mut().remove(uuid);
+                None
+            },
+            Step::Fail(uuid) => {
+                unicorn.failing.borrow_mut().insert(uuid.to_string());
+                None
+            },
+            Step::Recover(uuid) => {
+                unicorn.failing.borrow_mut().remove(uuid);
+                None
+            },
+            Step::Event(event) => Some(event),
+        })
+    }
+}
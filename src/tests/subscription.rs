@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+use futures::Future;
+
+use tokio_core::reactor::Core;
+
+use changes::ChangeKind;
+use config::{Config, Locator};
+use engine::{ClusterView, ClusterViews, NodesEvent, process_nodes, subscription};
+use health::{HealthState, SyncedHealth};
+use locator::make_resolved_service;
+use orca::{Apps, SyncedApps};
+use resources::NodeInfo;
+use secure::{TicketInfo, make_ticket_service};
+use snapshot::Publisher;
+
+use super::{CLUSTER, FakeUnicorn, Step, make_node, run_until};
+use super::cocaine::FakeCocaine;
+
+
+const KIDS: &str = "/zorca/kids";
+
+
+fn run_script(steps: Vec<Step>) -> (ClusterView, Arc<SyncedHealth>, Publisher) {
+    let mut core = Core::new().unwrap();
+    let unicorn = FakeUnicorn::new();
+
+    let view = ClusterView::new();
+    let health = Arc::new(SyncedHealth::new(HealthState::new(false, &[CLUSTER.to_string()])));
+
+    let mut views = ClusterViews::new();
+    views.insert(CLUSTER.to_string(), view.clone());
+    let publisher = Publisher::new(Arc::new(views), Arc::new(SyncedApps::new(Arc::new(Apps::new()))));
+
+    let work = process_nodes(
+        unicorn.script(steps),
+        unicorn.fetcher(),
+        CLUSTER.to_string(),
+        view.clone(),
+        Arc::clone(&health),
+        publisher.clone(),
+    );
+    core.run(work).expect("nodes processing has failed");
+
+    (view, health, publisher)
+}
+
+/// Spawns `subscription` to fake cocaine, returned flag is set once it ends.
+fn subscribe(core: &Core, cocaine: &FakeCocaine, view: &ClusterView, health: &Arc<SyncedHealth>, publisher: &Publisher)
+    -> Rc<Cell<bool>>
+{
+    let handle = core.handle();
+    let locator = Locator {
+        endpoints: vec![ cocaine.locator.to_string() ],
+        unicorn_service: "unicorn".to_string(),
+        tvm_service: "tvm".to_string(),
+    };
+
+    let config = Config::new_from_files(&[]).unwrap();
+    let proxy = make_ticket_service(&locator, &handle, &config, Arc::new(RwLock::new(TicketInfo::new())));
+    let unicorn = make_resolved_service(&locator.unicorn_service, &handle, &[ cocaine.locator ]).unwrap();
+
+    let work = subscription(
+        unicorn,
+        handle.clone(),
+        CLUSTER,
+        KIDS,
+        &locator,
+        vec![ cocaine.locator ],
+        view.clone(),
+        proxy,
+        Arc::clone(health),
+        publisher.clone(),
+    );
+
+    let ended = Rc::new(Cell::new(false));
+    let done = Rc::clone(&ended);
+    handle.spawn(work.then(move |result| {
+        println!("subscription has ended with {:?}", result);
+        done.set(true);
+        Ok(())
+    }));
+
+    ended
+}
+
+fn uuids_of(view: &ClusterView) -> Vec<String> {
+    let mut uuids: Vec<_> = view.cluster.read().unwrap().keys().cloned().collect();
+    uuids.sort();
+    uuids
+}
+
+fn node(hostname: &str) -> NodeInfo {
+    make_node(hostname, &"[::1]:10000".parse().unwrap())
+}
+
+#[test]
+fn tracks_joined_and_left_nodes() {
+    let (view, health, publisher) = run_script(vec![
+        Step::Put("a", node("host-a"), 1),
+        Step::Put("b", node("host-b"), 1),
+        Step::Event(NodesEvent::Update((10, vec!["a".to_string(), "b".to_string()]))),
+        Step::Remove("a"),
+        Step::Event(NodesEvent::Update((11, vec!["b".to_string()]))),
+        Step::Put("c", node("host-c"), 3),
+        Step::Event(NodesEvent::Update((12, vec!["b".to_string(), "c".to_string()]))),
+    ]);
+
+    let cluster = view.cluster.read().unwrap();
+    let mut uuids: Vec<_> = cluster.keys().cloned().collect();
+    uuids.sort();
+    assert_eq!(uuids, vec!["b", "c"]);
+
+    assert_eq!(cluster["c"].cluster, CLUSTER);
+    assert_eq!(cluster["c"].unicorn_version, Some(3));
+
+    let changelog = view.changes.read().unwrap();
+    let changes: Vec<_> = changelog.changes().iter()
+        .map(|change| (change.uuid.as_str(), change.kind, change.snapshot_version))
+        .collect();
+
+    assert_eq!(changes.len(), 4);
+    assert!(changes.contains(&("a", ChangeKind::Joined, Some(10))));
+    assert!(changes.contains(&("b", ChangeKind::Joined, Some(10))));
+    assert_eq!(changes[2], ("a", ChangeKind::Left, Some(11)));
+    assert_eq!(changes[3], ("c", ChangeKind::Joined, Some(12)));
+
+    let health = health.read().unwrap();
+    assert_eq!(health.subscription_versions[CLUSTER], 12);
+    assert!(health.subscriptions[CLUSTER].active);
+
+    // Every change is published, so snapshot is the same as working state.
+    let snapshot = publisher.load();
+    assert_eq!(snapshot.clusters[CLUSTER].cluster.len(), 2);
+    assert!(snapshot.clusters[CLUSTER].cluster.contains_key("c"));
+}
+
+#[test]
+fn skips_outdated_versions() {
+    let (view, _, _) = run_script(vec![
+        Step::Put("a", node("host-a"), 1),
+        Step::Event(NodesEvent::Update((5, vec!["a".to_string()]))),
+        Step::Event(NodesEvent::Update((4, vec![]))),
+    ]);
+
+    assert!(view.cluster.read().unwrap().contains_key("a"));
+}
+
+#[test]
+fn retries_failed_fetches() {
+    let (view, _, _) = run_script(vec![
+        Step::Put("a", node("host-a"), 1),
+        Step::Fail("a"),
+        Step::Event(NodesEvent::Update((1, vec!["a".to_string()]))),
+        Step::Recover("a"),
+        Step::Event(NodesEvent::Refresh),
+    ]);
+
+    let cluster = view.cluster.read().unwrap();
+    assert_eq!(cluster["a"].hostname, "host-a");
+}
+
+#[test]
+fn fails_when_whole_batch_failed() {
+    let (view, health, _) = run_script(vec![
+        Step::Put("a", node("host-a"), 1),
+        Step::Fail("a"),
+        Step::Event(NodesEvent::Update((1, vec!["a".to_string()]))),
+    ]);
+
+    assert!(view.cluster.read().unwrap().is_empty());
+
+    let health = health.read().unwrap();
+    assert!(!health.subscriptions[CLUSTER].active);
+    assert!(health.subscriptions[CLUSTER].last_error.is_some());
+}
+
+#[test]
+fn refreshed_changes_have_no_version() {
+    let (view, _, _) = run_script(vec![
+        Step::Put("a", node("host-a"), 1),
+        Step::Put("b", node("host-b"), 1),
+        Step::Fail("b"),
+        Step::Event(NodesEvent::Update((7, vec!["a".to_string(), "b".to_string()]))),
+        Step::Recover("b"),
+        Step::Event(NodesEvent::Refresh),
+    ]);
+
+    let changelog = view.changes.read().unwrap();
+    let changes: Vec<_> = changelog.changes().iter()
+        .map(|change| (change.uuid.as_str(), change.kind, change.snapshot_version))
+        .collect();
+
+    assert_eq!(changes, vec![("a", ChangeKind::Joined, Some(7)), ("b", ChangeKind::Joined, None)]);
+}
+
+#[test]
+fn follows_subscription_over_cocaine_protocol() {
+    let mut core = Core::new().unwrap();
+    let cocaine = FakeCocaine::new(&core, KIDS);
+
+    let view = ClusterView::new();
+    let health = Arc::new(SyncedHealth::new(HealthState::new(false, &[CLUSTER.to_string()])));
+
+    let mut views = ClusterViews::new();
+    views.insert(CLUSTER.to_string(), view.clone());
+    let publisher = Publisher::new(Arc::new(views), Arc::new(SyncedApps::new(Arc::new(Apps::new()))));
+
+    // Subscribe: nodes present on subscription are fetched.
+    cocaine.put("a", node("host-a"), 1);
+    cocaine.put("b", node("host-b"), 1);
+    cocaine.publish();
+
+    let ended = subscribe(&core, &cocaine, &view, &health, &publisher);
+    run_until(&mut core, "initial nodes", || uuids_of(&view) == vec!["a", "b"]);
+    assert_eq!(view.cluster.read().unwrap()["b"].hostname, "host-b");
+
+    // Update: pushed children are applied.
+    cocaine.remove("a");
+    cocaine.put("c", node("host-c"), 2);
+    cocaine.publish();
+
+    run_until(&mut core, "updated nodes", || uuids_of(&view) == vec!["b", "c"]);
+    assert_eq!(view.cluster.read().unwrap()["c"].unicorn_version, Some(2));
+    assert_eq!(health.read().unwrap().subscription_versions[CLUSTER], 2);
+
+    // Connection loss: subscription ends, so runner could make new one.
+    cocaine.break_connections();
+    run_until(&mut core, "subscription end", || ended.get());
+
+    // Resubscribe: changes made meanwhile are picked up.
+    cocaine.remove("b");
+    cocaine.publish();
+
+    let ended = subscribe(&core, &cocaine, &view, &health, &publisher);
+    run_until(&mut core, "resubscribed nodes", || uuids_of(&view) == vec!["c"]);
+    assert!(!ended.get());
+    assert_eq!(cocaine.subscriptions(), 2);
+    assert_eq!(health.read().unwrap().subscription_versions[CLUSTER], 3);
+
+    let changelog = view.changes.read().unwrap();
+    let changes: Vec<_> = changelog.changes().iter()
+        .map(|change| (change.uuid.as_str(), change.kind, change.snapshot_version))
+        .collect();
+
+    assert!(changes.contains(&("a", ChangeKind::Left, Some(2))));
+    assert!(changes.contains(&("c", ChangeKind::Joined, Some(2))));
+    assert!(changes.contains(&("b", ChangeKind::Left, Some(3))));
+
+    let snapshot = publisher.load();
+    assert_eq!(snapshot.clusters[CLUSTER].cluster.len(), 1);
+}
@@ -0,0 +1,409 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::Read;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use futures::{Future, Stream};
+
+use tokio_core::reactor::Core;
+
+use flate2::read::GzDecoder;
+
+use hyper::header::{
+    AcceptEncoding,
+    Allow,
+    ContentEncoding,
+    ContentLength,
+    ETag,
+    Encoding,
+    Headers,
+    IfNoneMatch,
+    qitem,
+};
+use hyper::{Method, Request, StatusCode};
+use hyper::server::Service;
+
+use serde_json::{self, Value};
+
+use base64;
+use sha1::Sha1;
+use time;
+
+use access::{Authenticator, Role};
+use breaker::BreakerConfig;
+use cache::{BodyEncoding, ResponseCache};
+use config::{WebAuth, WebCors};
+use engine::ClusterViews;
+use health::{HealthState, SyncedHealth};
+use lifecycle::Lifecycle;
+use orca::{self, AppsTrait, SyncedApps};
+use secure::{SyncedTicketInfo, TicketInfo};
+use simulator::{self, Scenario};
+use snapshot::Publisher;
+use web::{Model, SelfInfo, WebApi};
+
+use super::{CLUSTER, fake_fleet, fleet_view, gather_once, temp_file};
+
+
+// Web api over fleet gathered once, publisher is returned to update it.
+fn make_api(core: &mut Core, cors: Option<WebCors>) -> (WebApi, Publisher) {
+    make_clusters_api(core, &[CLUSTER], cors, Rc::new(Cell::new(0)), None)
+}
+
+// Web api requiring authentication, authenticator is returned to set ticket.
+fn make_auth_api(core: &mut Core, auth: WebAuth) -> (WebApi, Rc<Authenticator>) {
+    let auth = Rc::new(Authenticator::new(&auth).unwrap());
+    let (api, _) = make_clusters_api(core, &[CLUSTER], None, Rc::new(Cell::new(0)), Some(Rc::clone(&auth)));
+    (api, auth)
+}
+
+// The same fleet is listed in each of clusters.
+fn make_clusters_api(
+    core: &mut Core,
+    names: &[&str],
+    cors: Option<WebCors>,
+    in_flight: Rc<Cell<usize>>,
+    auth: Option<Rc<Authenticator>>)
+    -> (WebApi, Publisher)
+{
+    let addrs = fake_fleet(core, 2, Scenario::new());
+    let apps = Arc::new(SyncedApps::new(Arc::new(orca::Apps::new())));
+    let mut clusters = ClusterViews::new();
+
+    for name in names {
+        let view = fleet_view(&addrs);
+        gather_once(core, &view, BreakerConfig::new());
+
+        Arc::make_mut(&mut apps.write().unwrap()).merge(&view.orcas.read().unwrap());
+        clusters.insert(name.to_string(), view);
+    }
+    let clusters = Arc::new(clusters);
+    let cluster_names: Vec<_> = names.iter().map(|name| name.to_string()).collect();
+
+    let publisher = Publisher::new(Arc::clone(&clusters), apps);
+
+    let model = Model {
+        snapshots: publisher.clone(),
+        ticket: Arc::new(SyncedTicketInfo::new(TicketInfo::new())),
+        lifecycle: Arc::new(Lifecycle::new()),
+        health: Arc::new(SyncedHealth::new(HealthState::new(false, &cluster_names))),
+        self_info: SelfInfo::new("test", &[], json!({})),
+    };
+
+    let cache = Rc::new(RefCell::new(ResponseCache::new()));
+    let api = WebApi::new(&core.handle(), model, ".", auth, in_flight, cache, cors.map(Rc::new));
+
+    (api, publisher)
+}
+
+fn call(core: &mut Core, api: &WebApi, method: Method, path: &str, headers: Headers) -> (StatusCode, Headers, Vec<u8>) {
+    let mut request = Request::new(method, path.parse().unwrap());
+    *request.headers_mut() = headers;
+
+    let response = api.call(request)
+        .and_then(|response| {
+            let (status, headers) = (response.status(), response.headers().clone());
+            response.body().concat2().map(move |body| (status, headers, body.to_vec()))
+        });
+
+    core.run(response).expect("request has failed")
+}
+
+fn header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .and_then(|raw| raw.one())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+fn get(core: &mut Core, api: &WebApi, path: &str) -> (StatusCode, Option<Value>) {
+    let (status, _, body) = call(core, api, Method::Get, path, Headers::new());
+    (status, serde_json::from_slice(&body).ok())
+}
+
+#[test]
+fn serves_gathered_state() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_api(&mut core, None);
+
+    let (status, clusters) = get(&mut core, &api, "/api/v1/clusters");
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(clusters, Some(json!([CLUSTER])));
+
+    let (status, apps) = get(&mut core, &api, "/api/v1/apps");
+    assert_eq!(status, StatusCode::Ok);
+    let apps = apps.unwrap();
+    assert_eq!(apps.as_object().unwrap().len(), Scenario::new().apps);
+    assert!(apps["app0"]["hosts"][&simulator::hostname(0)].is_object());
+
+    let (status, orcas) = get(&mut core, &api, &format!("/api/v1/clusters/{}/orcas", CLUSTER));
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(orcas.unwrap().as_object().unwrap().len(), 2);
+
+    let (status, changes) = get(&mut core, &api, "/api/v1/cluster/changes");
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(changes.unwrap().as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn keeps_hosts_of_all_clusters() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_clusters_api(&mut core, &["first", "second"], None, Rc::new(Cell::new(0)), None);
+
+    // Both clusters list the same hosts, neither is lost on merge.
+    let (status, orcas) = get(&mut core, &api, "/api/v1/orcas");
+    assert_eq!(status, StatusCode::Ok);
+    let orcas = orcas.unwrap();
+    assert_eq!(orcas.as_object().unwrap().len(), 4);
+    assert!(orcas[&format!("first/{}", simulator::hostname(0))].is_object());
+    assert!(orcas[&format!("second/{}", simulator::hostname(0))].is_object());
+
+    // Single cluster is keyed by host as is.
+    let (_, orcas) = get(&mut core, &api, "/api/v1/clusters/first/orcas");
+    assert!(orcas.unwrap()[&simulator::hostname(0)].is_object());
+}
+
+#[test]
+fn unknown_cluster_is_not_found() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_api(&mut core, None);
+
+    let (status, error) = get(&mut core, &api, "/api/v1/clusters/unknown/apps");
+    assert_eq!(status, StatusCode::NotFound);
+    assert_eq!(error, Some(json!({ "error": { "status": 404, "message": "Not Found" } })));
+
+    let (status, error) = get(&mut core, &api, "/api/v1/unknown");
+    assert_eq!(status, StatusCode::NotFound);
+    assert_eq!(error.unwrap()["error"]["status"], json!(404));
+}
+
+#[test]
+fn handles_methods() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_api(&mut core, None);
+    let allowed = Allow(vec![Method::Get, Method::Head, Method::Options]);
+
+    let (status, headers, body) = call(&mut core, &api, Method::Post, "/api/v1/apps", Headers::new());
+    assert_eq!(status, StatusCode::MethodNotAllowed);
+    assert_eq!(headers.get::<Allow>(), Some(&allowed));
+    assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["error"]["status"], json!(405));
+
+    let (status, headers, _) = call(&mut core, &api, Method::Options, "/api/v1/apps", Headers::new());
+    assert_eq!(status, StatusCode::NoContent);
+    assert_eq!(headers.get::<Allow>(), Some(&allowed));
+
+    // Unknown path is reported as such whatever the method is.
+    for method in vec![Method::Post, Method::Options] {
+        let (status, _, _) = call(&mut core, &api, method, "/api/v1/nonexistent", Headers::new());
+        assert_eq!(status, StatusCode::NotFound);
+    }
+
+    // Head reply is get one without body.
+    let (_, _, identity) = call(&mut core, &api, Method::Get, "/api/v1/apps", Headers::new());
+    let (status, headers, body) = call(&mut core, &api, Method::Head, "/api/v1/apps", Headers::new());
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(headers.get::<ContentLength>(), Some(&ContentLength(identity.len() as u64)));
+    assert!(body.is_empty());
+}
+
+#[test]
+fn allows_configured_origins() {
+    let mut core = Core::new().unwrap();
+    let cors = WebCors {
+        origins: vec![ "https://dashboard.local".to_string() ],
+        credentials: true,
+        max_age_sec: 60,
+    };
+    let (api, _) = make_api(&mut core, Some(cors));
+
+    let with_origin = |origin: &'static str| {
+        let mut headers = Headers::new();
+        headers.set_raw("Origin", origin);
+        headers
+    };
+
+    let (status, headers, _) = call(&mut core, &api, Method::Options, "/api/v1/apps", with_origin("https://dashboard.local"));
+    assert_eq!(status, StatusCode::NoContent);
+    assert_eq!(header(&headers, "Access-Control-Allow-Origin"), Some("https://dashboard.local".to_string()));
+    assert_eq!(header(&headers, "Access-Control-Allow-Credentials"), Some("true".to_string()));
+    assert_eq!(header(&headers, "Access-Control-Max-Age"), Some("60".to_string()));
+
+    let (status, headers, _) = call(&mut core, &api, Method::Get, "/api/v1/apps", with_origin("https://dashboard.local"));
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(header(&headers, "Access-Control-Allow-Origin"), Some("https://dashboard.local".to_string()));
+
+    let (status, headers, _) = call(&mut core, &api, Method::Get, "/api/v1/apps", with_origin("https://evil.local"));
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(header(&headers, "Access-Control-Allow-Origin"), None);
+}
+
+#[test]
+fn conditional_requests_follow_snapshots() {
+    let mut core = Core::new().unwrap();
+    let (api, publisher) = make_api(&mut core, None);
+
+    let (status, headers, _) = call(&mut core, &api, Method::Get, "/api/v1/apps", Headers::new());
+    assert_eq!(status, StatusCode::Ok);
+    let etag = headers.get::<ETag>().cloned().expect("no etag in response").0;
+
+    let mut conditional = Headers::new();
+    conditional.set(IfNoneMatch::Items(vec![etag.clone()]));
+
+    let (status, _, body) = call(&mut core, &api, Method::Get, "/api/v1/apps", conditional.clone());
+    assert_eq!(status, StatusCode::NotModified);
+    assert!(body.is_empty());
+
+    // New snapshot with the same state keeps tag of body.
+    publisher.publish_gathered();
+
+    let (status, headers, _) = call(&mut core, &api, Method::Get, "/api/v1/apps", conditional);
+    assert_eq!(status, StatusCode::NotModified);
+    assert_eq!(headers.get::<ETag>().map(|tag| &tag.0), Some(&etag));
+}
+
+#[test]
+fn validators_change_with_body_only() {
+    let mut cache = ResponseCache::new();
+    let mut get = |generation, timestamp, body: &str| {
+        let body = body.to_string();
+        cache.get(generation, timestamp, "/api/v1/apps", BodyEncoding::Identity, || Ok::<_, ()>(Some(body)))
+            .unwrap()
+            .unwrap()
+    };
+
+    let first = get(1, 100, "{}");
+    let same = get(2, 200, "{}");
+    assert_eq!(same.etag, first.etag);
+    assert_eq!(same.modified, 100);
+
+    let changed = get(3, 300, "{\"app\": {}}");
+    assert!(changed.etag != first.etag);
+    assert_eq!(changed.modified, 300);
+    assert_eq!(&changed.body[..], b"{\"app\": {}}");
+}
+
+#[test]
+fn compresses_negotiated_responses() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_api(&mut core, None);
+
+    let (_, _, identity) = call(&mut core, &api, Method::Get, "/api/v1/apps", Headers::new());
+
+    let mut accept = Headers::new();
+    accept.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+
+    let (status, headers, body) = call(&mut core, &api, Method::Get, "/api/v1/apps", accept);
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+
+    let mut decoded = Vec::new();
+    GzDecoder::new(&body[..]).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, identity);
+}
+
+#[test]
+fn dropped_request_is_not_in_flight() {
+    let mut core = Core::new().unwrap();
+    let in_flight = Rc::new(Cell::new(0));
+    let (api, _) = make_clusters_api(&mut core, &[CLUSTER], None, Rc::clone(&in_flight), None);
+
+    let response = api.call(Request::new(Method::Get, "/api/v1/apps".parse().unwrap()));
+    assert_eq!(in_flight.get(), 1);
+
+    // Response future of disconnected client is dropped by hyper.
+    drop(response);
+    assert_eq!(in_flight.get(), 0);
+}
+
+// Viewer and admin tokens, peers with zorca's ticket are admins.
+fn web_auth() -> WebAuth {
+    let mut tokens = HashMap::new();
+    tokens.insert("viewer-token".to_string(), Role::Viewer);
+    tokens.insert("admin-token".to_string(), Role::Admin);
+
+    WebAuth {
+        tokens,
+        htpasswd: None,
+        users: HashMap::new(),
+        ticket_role: Some(Role::Admin),
+        anonymous_role: None,
+    }
+}
+
+fn status_as(core: &mut Core, api: &WebApi, path: &str, authorization: Option<&str>) -> StatusCode {
+    let mut headers = Headers::new();
+    if let Some(authorization) = authorization {
+        headers.set_raw("Authorization", authorization.to_string());
+    }
+
+    call(core, api, Method::Get, path, headers).0
+}
+
+#[test]
+fn token_roles_limit_sensitive_functions() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_auth_api(&mut core, web_auth());
+
+    assert_eq!(status_as(&mut core, &api, "/api/v1/apps", None), StatusCode::Unauthorized);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/apps", Some("Bearer unknown")), StatusCode::Unauthorized);
+
+    assert_eq!(status_as(&mut core, &api, "/api/v1/apps", Some("Bearer viewer-token")), StatusCode::Ok);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer viewer-token")), StatusCode::Forbidden);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer admin-token")), StatusCode::Ok);
+}
+
+#[test]
+fn anonymous_role_applies_without_credentials() {
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_auth_api(&mut core, WebAuth { anonymous_role: Some(Role::Viewer), ..web_auth() });
+
+    assert_eq!(status_as(&mut core, &api, "/api/v1/apps", None), StatusCode::Ok);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", None), StatusCode::Forbidden);
+}
+
+#[test]
+fn basic_auth_checks_htpasswd() {
+    let mut sha = Sha1::new();
+    sha.update(b"secret");
+    let hash = base64::encode(&sha.digest().bytes());
+
+    let htpasswd = format!("admin:{{SHA}}{}\nviewer:{{SHA}}{}\n", hash, hash);
+    let mut users = HashMap::new();
+    users.insert("admin".to_string(), Role::Admin);
+
+    let auth = WebAuth {
+        htpasswd: Some(temp_file("htpasswd", htpasswd.as_bytes())),
+        users,
+        ..web_auth()
+    };
+
+    let mut core = Core::new().unwrap();
+    let (api, _) = make_auth_api(&mut core, auth);
+
+    let basic = |credentials: &str| format!("Basic {}", base64::encode(credentials));
+
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some(basic("admin:secret").as_str())), StatusCode::Ok);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some(basic("viewer:secret").as_str())), StatusCode::Forbidden);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/apps", Some(basic("admin:wrong").as_str())), StatusCode::Unauthorized);
+}
+
+#[test]
+fn bearer_ticket_is_accepted_till_expired() {
+    let mut core = Core::new().unwrap();
+    let (api, auth) = make_auth_api(&mut core, web_auth());
+    let now = time::get_time().sec;
+
+    // Tickets of secure backends use the same scheme as static tokens.
+    auth.set_ticket(Some("Bearer first".to_string()), 0);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer first")), StatusCode::Ok);
+
+    // Replaced ticket is still held by peers.
+    auth.set_ticket(Some("Bearer second".to_string()), now + 60);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer first")), StatusCode::Ok);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer second")), StatusCode::Ok);
+
+    auth.set_ticket(Some("Bearer third".to_string()), now - 1);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer first")), StatusCode::Unauthorized);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer second")), StatusCode::Unauthorized);
+    assert_eq!(status_as(&mut core, &api, "/api/v1/state", Some("Bearer third")), StatusCode::Ok);
+}
@@ -13,19 +13,21 @@ use errors::CombinedError;
 use engine::SubscribeMessage;
 
 
-pub fn kids_subscribe<'a, H, Q>(unicorn: &'a Unicorn, path: String, headers: H, sender: Q)
+/// Subscribes to children of node, service is kept till subscription ends.
+pub fn kids_subscribe<'a, H, Q>(service: Service, path: String, headers: H, sender: Q)
     -> Box<Future<Item=(), Error=CombinedError> + 'a>
 where
     H: Into<Option<Vec<RawHeader>>> + 'a,
     Q: Sink<SinkItem=SubscribeMessage, SinkError=SendError<SubscribeMessage>> + 'a
 {
-    let subscription = unicorn.children_subscribe(&path, headers)
+    let subscription = Unicorn::new(service.clone()).children_subscribe(&path, headers)
         .map_err(CombinedError::CocaineError)
         .and_then(move |(tx, stream)| {
             sender.sink_map_err(CombinedError::QueueSendError)
                 .send_all(stream)
                 .and_then(move |_| {
                     drop(tx);
+                    drop(service);
                     Ok(())
                 })
         });
@@ -33,16 +35,16 @@ where
     Box::new(subscription)
 }
 
+/// Gets node data along with its unicorn version.
 pub fn get_node<'a, H, T>(service: Service, headers: H, path: &str)
-    -> Box<Future<Item=Option<T>, Error=CombinedError> + 'a>
+    -> Box<Future<Item=(Option<T>, i64), Error=CombinedError> + 'a>
 where
     H: Into<Option<Vec<RawHeader>>> + 'a,
     T: for<'de> Deserialize<'de> + Send + Debug + 'a,
 {
     let future = Unicorn::new(service)
         .get::<T,_>(path, headers)
-        .map_err(CombinedError::CocaineError)
-        .and_then(|(data, _version)| Ok(data));
+        .map_err(CombinedError::CocaineError);
 
     Box::new(future)
 }
@@ -1,8 +1,8 @@
 //
 // TODO: ugly & dirty fast coded implementation, rewrite/refactor someday.
-// TODO: cache serialized strings, update on change?
 //
 use futures::{Future, future};
+use bytes::Bytes;
 use tokio_core::reactor::Handle;
 
 use serde;
@@ -10,79 +10,226 @@ use serde_json;
 
 use hyper_staticfile::Static;
 
-use hyper::header::{ContentLength, ContentType};
+use hyper::header::{
+    AcceptEncoding,
+    Allow,
+    ContentEncoding,
+    ContentLength,
+    ContentType,
+    ETag,
+    EntityTag,
+    Headers,
+    HttpDate,
+    IfModifiedSince,
+    IfNoneMatch,
+    LastModified,
+};
 use hyper::server::{Request, Response, Service};
 use hyper::{
+    Body,
     Error,
     Method,
     StatusCode
 };
 
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
+use std::rc::Rc;
+use std::cell::Cell;
 use std::path::Path;
-use std::ops::Deref;
-use std::collections::VecDeque;
-use std::time::{self, UNIX_EPOCH};
-
-use engine::SyncedCluster;
-use orca::{
-    SyncedOrcasPod,
-    SyncedApps
-};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::str;
+use std::time::{self, Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use config::{ClusterSource, WebCors};
+use changes::Change;
+use orca::{Apps, CommittedState};
+use secure::{TicketInfo, SyncedTicketInfo};
+use access::{Authenticator, Role};
+use lifecycle::Lifecycle;
+use health::{Report, SyncedHealth};
+use snapshot::{ClusterSnapshot, Publisher, Snapshot};
+use cache::{BodyEncoding, SharedResponseCache};
 
 
 const API_V1: &str = "v1";
-static NOT_FOUND: &str = "<html>\
-    <body>\
-    <h1>Page not found</h1>\
-    </body>\
-    </html>";
+// Function name for `cluster/changes` path.
+const CHANGES: &str = "cluster/changes";
+// Functions served only for all clusters.
+const GLOBAL_FUNCTIONS: &[&str] = &["clusters", "ticket", "self"];
+// Functions served both for single cluster and for all of them merged.
+const CLUSTER_FUNCTIONS: &[&str] = &["apps", "cluster", "orcas", "pod", "breakers", "state", CHANGES];
+// Errors of paths under prefix are served as json.
+const API_PREFIX: &str = "/api/";
+// Methods every route is served with.
+const ALLOWED_METHODS: &[Method] = &[Method::Get, Method::Head, Method::Options];
+// Request headers dashboard is allowed to send cross-origin.
+const CORS_ALLOWED_HEADERS: &str = "Authorization, If-None-Match, If-Modified-Since";
+const CORS_EXPOSED_HEADERS: &str = "ETag, Last-Modified";
 
 
 type BoxedResponseFuture = Box<Future<Item=Response, Error=Error>>;
 
 #[derive(Clone)]
 pub struct Model {
-    pub cluster: Arc<SyncedCluster>,
-    pub orcas: Arc<SyncedOrcasPod>,
-    pub apps: Arc<SyncedApps>,
+    pub snapshots: Publisher,
+    pub ticket: Arc<SyncedTicketInfo>,
+    pub lifecycle: Arc<Lifecycle>,
+    pub health: Arc<SyncedHealth>,
 
     pub self_info: SelfInfo,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+pub struct BuildInfo {
+    git_commit: String,
+    build_time: u64,
+    rustc_version: String,
+}
+
+impl BuildInfo {
+    pub fn new() -> BuildInfo {
+        BuildInfo {
+            git_commit: env!("ZORCA_GIT_COMMIT").to_string(),
+            build_time: env!("ZORCA_BUILD_TIME").parse().unwrap_or(0),
+            rustc_version: env!("ZORCA_RUSTC_VERSION").to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SelfInfo {
     start_time: u64,
-    uptime: u64,
     version: String,
+    build: BuildInfo,
+    // mapping: cluster name -> nodes source description
+    sources: BTreeMap<String, String>,
+    // Effective config with secrets redacted.
+    config: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct SubscriptionReport {
+    established: bool,
+    last_version: i64,
+    last_update: u64,
+}
+
+#[derive(Serialize)]
+struct GatherReport {
+    cycles: u64,
+    last_duration_ms: u64,
+    last_success: u64,
+}
+
+#[derive(Serialize)]
+struct Sizes {
+    cluster: usize,
+    pod: usize,
+    apps: usize,
+}
+
+#[derive(Serialize)]
+struct SelfReport<'a> {
+    start_time: u64,
+    uptime: u64,
+    version: &'a str,
+    build: &'a BuildInfo,
+    sources: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<&'a serde_json::Value>,
+    // mapping: cluster name -> subscription state
+    subscriptions: BTreeMap<&'a String, SubscriptionReport>,
+    gather: GatherReport,
+    sizes: Sizes,
+    snapshot_time: u64,
+    threads: Report,
 }
 
 impl SelfInfo {
-    pub fn new(version: &str) -> SelfInfo {
+    pub fn new(version: &str, sources: &[ClusterSource], config: serde_json::Value) -> SelfInfo {
         let start_time = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let start_time = start_time.as_secs();
         let version = version.to_string();
+        let sources = sources.iter()
+            .map(|source| (source.name.clone(), source.describe()))
+            .collect();
 
-        SelfInfo {start_time, version, uptime: 0}
+        SelfInfo {start_time, version, build: BuildInfo::new(), sources, config}
     }
+}
 
-    pub fn as_json_response(&self) -> BoxedResponseFuture
-    {
-        let mut response = Response::new();
-        let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let now = now.as_secs();
+// Config is shown to admins only.
+fn self_as_json_response(model: &Model, with_config: bool) -> BoxedResponseFuture
+{
+    let mut response = Response::new();
+    let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let now = now.as_secs();
 
-        let to_display = SelfInfo { uptime: now - self.start_time, ..self.clone() };
-        set_json_body(&mut response, &to_display);
+    let info = &model.self_info;
+    let snapshot = model.snapshots.load();
+    let health = model.health.read().unwrap();
 
-        Box::new(future::ok(response))
-    }
+    let sizes = Sizes {
+        cluster: snapshot.clusters.values().map(|cluster| cluster.cluster.len()).sum(),
+        pod: snapshot.clusters.values().map(|cluster| cluster.orcas.len()).sum(),
+        apps: snapshot.apps.len(),
+    };
+
+    let subscriptions = health.subscriptions.iter()
+        .map(|(name, state)| (name, SubscriptionReport {
+            established: state.active,
+            last_version: health.subscription_versions.get(name).cloned().unwrap_or(-1),
+            last_update: state.last_success,
+        }))
+        .collect();
+
+    let to_display = SelfReport {
+        start_time: info.start_time,
+        uptime: now - info.start_time,
+        version: &info.version,
+        build: &info.build,
+        sources: &info.sources,
+        config: if with_config { Some(&info.config) } else { None },
+        subscriptions,
+        gather: GatherReport {
+            cycles: health.gather_cycles,
+            last_duration_ms: health.gather_duration_ms,
+            last_success: health.gather.last_success,
+        },
+        sizes,
+        snapshot_time: snapshot.timestamp,
+        threads: health.liveness(),
+    };
 
+    set_json_body(&mut response, &to_display);
+
+    Box::new(future::ok(response))
+}
+
+fn ticket_as_json_response(ticket: &SyncedTicketInfo) -> BoxedResponseFuture {
+    let mut response = Response::new();
+    let now = time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let now = now.as_secs() as i64;
+
+    let ticket = ticket.read().unwrap();
+    let age = if ticket.last_updated > 0 { now - ticket.last_updated } else { 0 };
+
+    let to_display = TicketInfo { age, ..ticket.clone() };
+    set_json_body(&mut response, &to_display);
+
+    Box::new(future::ok(response))
 }
 
 #[derive(Debug)]
 enum Route<'a> {
+    // Merged view of all clusters.
     Api(&'a str, &'a str),
+    // View of single cluster: version, cluster name, function.
+    ClusterApi(&'a str, &'a str, &'a str),
+    // Liveness and readiness probes, served without authentication.
+    Health(&'a str),
     Asset(&'a str),
 }
 
@@ -96,105 +243,448 @@ fn parse_path(path: &str) -> Route {
 
     match (parts.len(), parts.front()) {
         (3, Some(&"api")) => Route::Api(parts[1], parts[2]),
+        (4, Some(&"api")) if parts[2] == "cluster" && parts[3] == "changes" => Route::Api(parts[1], CHANGES),
+        (5, Some(&"api")) if parts[2] == "clusters" => Route::ClusterApi(parts[1], parts[3], parts[4]),
+        (6, Some(&"api")) if parts[2] == "clusters" && parts[4] == "cluster" && parts[5] == "changes" =>
+            Route::ClusterApi(parts[1], parts[3], CHANGES),
+        (1, Some(&"healthz")) | (1, Some(&"readyz")) => Route::Health(parts[0]),
         _ => Route::Asset(path)
     }
 }
 
-fn as_not_found(_path: &str)
-    -> BoxedResponseFuture
-{
+// Api clients get errors as json, browsers get html pages.
+fn error_response(path: &str, status: StatusCode) -> Response {
     let mut response = Response::new();
 
-    response.set_status(StatusCode::NotFound);
-    response.set_body(NOT_FOUND);
+    if status == StatusCode::Unauthorized {
+        response.headers_mut().set_raw("WWW-Authenticate", r#"Basic realm="zorca""#);
+    }
+
+    if path.starts_with(API_PREFIX) {
+        set_json_error(&mut response, status);
+    } else {
+        let reason = status.canonical_reason().unwrap_or("Unknown Error");
+        let body = format!("<html><body><h1>{}</h1></body></html>", reason);
+
+        response.set_status(status);
+        response.headers_mut().set(ContentType::html());
+        response.headers_mut().set(ContentLength(body.len() as u64));
+        response.set_body(body);
+    }
+
+    response
+}
+
+fn as_error(path: &str, status: StatusCode) -> BoxedResponseFuture {
+    Box::new(future::ok(error_response(path, status)))
+}
+
+fn as_not_allowed(path: &str) -> BoxedResponseFuture {
+    let mut response = error_response(path, StatusCode::MethodNotAllowed);
+    response.headers_mut().set(Allow(ALLOWED_METHODS.to_vec()));
 
     Box::new(future::ok(response))
 }
 
-fn as_json_locked<T>(item: &RwLock<T>)
-    -> BoxedResponseFuture
-where
-    T: serde::ser::Serialize
-{
+// Every route is served with the same methods, so no route lookup here.
+fn as_options() -> BoxedResponseFuture {
+    let mut response = Response::new();
+
+    response.set_status(StatusCode::NoContent);
+    response.headers_mut().set(Allow(ALLOWED_METHODS.to_vec()));
+
+    Box::new(future::ok(response))
+}
+
+// Origin is allowed if listed, wildcard allows any.
+fn set_cors_headers(response: &mut Response, cors: &WebCors, origin: &str, preflight: bool) {
+    let allowed = if cors.origins.iter().any(|allowed| allowed == origin) {
+        origin.to_string()
+    } else if cors.origins.iter().any(|allowed| allowed == "*") {
+        "*".to_string()
+    } else {
+        return;
+    };
+
+    let headers = response.headers_mut();
+
+    if allowed != "*" {
+        headers.append_raw("Vary", "Origin");
+    }
+    headers.set_raw("Access-Control-Allow-Origin", allowed);
+
+    if cors.credentials {
+        headers.set_raw("Access-Control-Allow-Credentials", "true");
+    }
+
+    if preflight {
+        let methods: Vec<_> = ALLOWED_METHODS.iter().map(|method| method.to_string()).collect();
+        headers.set_raw("Access-Control-Allow-Methods", methods.join(", "));
+        headers.set_raw("Access-Control-Allow-Headers", CORS_ALLOWED_HEADERS);
+        headers.set_raw("Access-Control-Max-Age", cors.max_age_sec.to_string());
+    } else {
+        headers.set_raw("Access-Control-Expose-Headers", CORS_EXPOSED_HEADERS);
+    }
+}
+
+// Minimal role required to access route.
+fn required_role(route: &Route) -> Role {
+    match *route {
+        Route::Api(API_V1, "state") | Route::Api(API_V1, "ticket") => Role::Admin,
+        Route::ClusterApi(API_V1, _, "state") => Role::Admin,
+        _ => Role::Viewer
+    }
+}
+
+fn health_as_json_response(probe: &str, model: &Model) -> BoxedResponseFuture {
     let mut response = Response::new();
 
-    let unlocked = item.read().unwrap();
-    set_json_body(&mut response, unlocked.deref());
+    let report = {
+        let health = model.health.read().unwrap();
+        match probe {
+            "readyz" => health.readiness(&model.ticket.read().unwrap(), model.lifecycle.is_stopping()),
+            _ => health.liveness(),
+        }
+    };
+
+    if !report.ok {
+        response.set_status(StatusCode::ServiceUnavailable);
+    }
+    set_json_body(&mut response, &report);
 
     Box::new(future::ok(response))
 }
 
-#[allow(dead_code)]
-fn as_json<T>(item: Arc<T>) -> BoxedResponseFuture
+// Cluster snapshot along with cluster name.
+type NamedCluster<'a> = (&'a str, &'a ClusterSnapshot);
+
+// Union of per-cluster maps, mapping: key -> value
+//
+// Note: the same host could be listed in several clusters, so keys of
+//       several clusters merged are prefixed with cluster name, as in
+//       `cluster/host`, single cluster ones are left as is.
+fn merged<'a, K, V, F>(clusters: &[NamedCluster<'a>], select: F) -> HashMap<String, &'a V>
 where
-    T: serde::ser::Serialize
+    K: Eq + Hash + Display + 'a,
+    F: Fn(&'a ClusterSnapshot) -> &'a HashMap<K, V>
 {
+    let qualified = clusters.len() > 1;
+
+    clusters.iter()
+        .flat_map(|&(name, cluster)| select(cluster).iter().map(move |(key, value)| match qualified {
+            true => (format!("{}/{}", name, key), value),
+            false => (key.to_string(), value),
+        }))
+        .collect()
+}
+
+// Functions served from snapshot both for single cluster and for all of
+// them merged, `None` if there is no such function.
+fn snapshot_api_body(clusters: &[NamedCluster], apps: &Apps, func: &str)
+    -> serde_json::Result<Option<String>>
+{
+    let body = match func {
+        "apps"    => serde_json::to_string(apps)?,
+        "cluster" => serde_json::to_string(&merged(clusters, |cluster| &*cluster.cluster))?,
+        "orcas" | "pod"
+                  => serde_json::to_string(&merged(clusters, |cluster| &*cluster.orcas))?,
+        "breakers" => serde_json::to_string(&merged(clusters, |cluster| &*cluster.breakers))?,
+        "state"   => {
+            let state: HashMap<_, &CommittedState> = merged(clusters, |cluster| &*cluster.orcas).into_iter()
+                .map(|(host, record)| (host, &record.orca.committed_state))
+                .collect();
+            serde_json::to_string(&state)?
+        },
+        // Changes of all clusters ordered by time.
+        CHANGES   => {
+            let mut changes: Vec<&Change> = clusters.iter()
+                .flat_map(|&(_, cluster)| cluster.changes.iter())
+                .collect();
+            changes.sort_by_key(|change| change.timestamp);
+            serde_json::to_string(&changes)?
+        },
+        _ => return Ok(None)
+    };
+
+    Ok(Some(body))
+}
+
+fn clusters_as_json(snapshot: &Snapshot) -> BoxedResponseFuture {
     let mut response = Response::new();
-    set_json_body(&mut response, item.as_ref());
+
+    let names: Vec<&String> = snapshot.clusters.keys().collect();
+    set_json_body(&mut response, &names);
+
     Box::new(future::ok(response))
 }
 
+// If-None-Match takes precedence over If-Modified-Since, as RFC 7232 says.
+fn is_not_modified(headers: &Headers, etag: &EntityTag, modified: SystemTime) -> bool {
+    if let Some(matches) = headers.get::<IfNoneMatch>() {
+        return match *matches {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+
+    match headers.get::<IfModifiedSince>() {
+        Some(&IfModifiedSince(since)) => SystemTime::from(since) >= modified,
+        None => false,
+    }
+}
+
+// Serialization failure is reported as internal error, status set so far
+// is replaced.
 fn set_json_body<T>(response: &mut Response, item: &T)
 where
     T: serde::ser::Serialize
 {
-    let body = match serde_json::to_string(item) {
-        Ok(b) => b,
-        Err(_) => r#"{"error": "internal error"}"#.into()
-    };
+    match serde_json::to_string(item) {
+        Ok(body) => set_encoded_body(response, Bytes::from(body.into_bytes()), BodyEncoding::Identity),
+        Err(e) => {
+            println!("failed to serialize response: {}", e);
+            set_json_error(response, StatusCode::InternalServerError);
+        }
+    }
+}
 
-    let len = body.len();
+// Error envelope: {"error": {"status": 404, "message": "Not Found"}}
+fn set_json_error(response: &mut Response, status: StatusCode) {
+    let message = status.canonical_reason().unwrap_or("Unknown Error");
+    let body = json!({ "error": { "status": status.as_u16(), "message": message } }).to_string();
+
+    response.set_status(status);
+    set_encoded_body(response, Bytes::from(body.into_bytes()), BodyEncoding::Identity);
+}
+
+fn set_encoded_body(response: &mut Response, body: Bytes, encoding: BodyEncoding) {
+    {
+        let headers = response.headers_mut();
+        headers.set(ContentType::json());
+        headers.set(ContentLength(body.len() as u64));
+        if let Some(header) = encoding.header() {
+            headers.set(ContentEncoding(vec![header]));
+        }
+    }
 
     response.set_body(body);
-    response.headers_mut().set(ContentType::json());
-    response.headers_mut().set(ContentLength(len as u64))
+}
+
+// Counts request as in flight till dropped, hyper drops response future of
+// disconnected client without completing it.
+struct InFlight(Rc<Cell<usize>>);
+
+impl InFlight {
+    fn new(counter: &Rc<Cell<usize>>) -> InFlight {
+        counter.set(counter.get() + 1);
+        InFlight(Rc::clone(counter))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
 }
 
 pub struct WebApi {
     model: Model,
     static_content: Static,
+    auth: Option<Rc<Authenticator>>,
+    // Number of requests being processed, shared by all connections.
+    in_flight: Rc<Cell<usize>>,
+    // Serialized snapshot responses, shared by all connections.
+    cache: SharedResponseCache,
+    cors: Option<Rc<WebCors>>,
 }
 
 impl WebApi {
-    pub fn new(handle: &Handle, model: Model, static_path: &str) -> WebApi {
+    pub fn new(
+        handle: &Handle,
+        model: Model,
+        static_path: &str,
+        auth: Option<Rc<Authenticator>>,
+        in_flight: Rc<Cell<usize>>,
+        cache: SharedResponseCache,
+        cors: Option<Rc<WebCors>>) -> WebApi
+    {
         WebApi {
             model,
-            static_content: Static::new(handle, Path::new(static_path))
+            static_content: Static::new(handle, Path::new(static_path)),
+            auth,
+            in_flight,
+            cache,
+            cors,
         }
     }
-}
 
-impl Service for WebApi {
-    type Response = Response;
-    type Request = Request;
-    type Error = Error;
-    type Future = BoxedResponseFuture;
+    // Functions served both for single cluster and for all of them merged.
+    fn cluster_api(
+        &self,
+        request: &Request,
+        snapshot: &Snapshot,
+        clusters: &[NamedCluster],
+        apps: &Apps,
+        func: &str)
+        -> BoxedResponseFuture
+    {
+        let (headers, path) = (request.headers(), request.path());
+        let encoding = BodyEncoding::negotiate(headers.get::<AcceptEncoding>());
+
+        let cached = self.cache.borrow_mut().get(snapshot.generation, snapshot.timestamp, path, encoding, || {
+            snapshot_api_body(clusters, apps, func)
+        });
+
+        let cached = match cached {
+            Ok(Some(cached)) => cached,
+            Ok(None) => return as_error(path, StatusCode::NotFound),
+            Err(e) => {
+                println!("failed to serialize {}: {}", path, e);
+                return as_error(path, StatusCode::InternalServerError);
+            }
+        };
 
-    fn call(&self, request: Request) -> Self::Future {
+        let etag = EntityTag::strong(cached.etag);
+        let modified = UNIX_EPOCH + Duration::from_secs(cached.modified);
+
+        let mut response = Response::new();
+        response.headers_mut().set(ETag(etag.clone()));
+        response.headers_mut().set(LastModified(HttpDate::from(modified)));
+        response.headers_mut().set_raw("Vary", "Accept-Encoding");
+
+        if is_not_modified(headers, &etag, modified) {
+            response.set_status(StatusCode::NotModified);
+        } else {
+            set_encoded_body(&mut response, cached.body, encoding);
+        }
+
+        Box::new(future::ok(response))
+    }
+
+    // Whether route points to existing resource, assets are looked up on
+    // serving.
+    fn route_exists(&self, route: &Route) -> bool {
+        match *route {
+            Route::Health(_) | Route::Asset(_) => true,
+            Route::Api(API_V1, func) => GLOBAL_FUNCTIONS.contains(&func) || CLUSTER_FUNCTIONS.contains(&func),
+            Route::ClusterApi(API_V1, name, func) =>
+                self.model.snapshots.load().clusters.contains_key(name) && CLUSTER_FUNCTIONS.contains(&func),
+            _ => false
+        }
+    }
+
+    fn route(&self, request: Request) -> BoxedResponseFuture {
         let path = request.path().to_string();
         let command = parse_path(&path);
 
-        let response = match (request.method(), command) {
+        if !self.route_exists(&command) {
+            return as_error(&path, StatusCode::NotFound);
+        }
+
+        // Preflight requests carry no credentials, so methods are checked
+        // before authentication.
+        match *request.method() {
+            Method::Get | Method::Head => {},
+            Method::Options => return as_options(),
+            _ => return as_not_allowed(&path),
+        };
+
+        // Everything is allowed if authentication isn't configured.
+        let role = match (self.auth.as_ref(), &command) {
+            (_, &Route::Health(_)) => Some(Role::Viewer),
+            (Some(auth), _) => auth.authenticate(request.headers()),
+            (None, _) => Some(Role::Admin)
+        };
+
+        match role {
+            None => return as_error(&path, StatusCode::Unauthorized),
+            Some(role) if role < required_role(&command) => return as_error(&path, StatusCode::Forbidden),
+            _ => {}
+        };
+
+        // Head requests are served as get ones, body is dropped on reply.
+        let response = match command {
+
+            Route::Health(probe) => health_as_json_response(probe, &self.model),
 
             // Serve static content.
-            (&Method::Get, Route::Asset(_asset)) => {
-                println!("asset is {:?}", _asset);
-                self.static_content.call(request)
-            },
+            Route::Asset(_) => self.static_content.call(request),
 
             // Basic api implementation.
-            (&Method::Get, Route::Api(ver, func)) => match (ver, func) {
-                (API_V1, "apps")    => as_json_locked(self.model.apps.as_ref()),
-                (API_V1, "cluster") => as_json_locked(self.model.cluster.as_ref()),
-                (API_V1, "orcas") | (API_V1, "pod")
-                                    => as_json_locked(self.model.orcas.as_ref()),
-                (API_V1, "self")    => self.model.self_info.as_json_response(), // TODO: self info: version etc
-                _ => as_not_found(&path)
+            Route::Api(ver, func) => match (ver, func) {
+                (API_V1, "clusters") => clusters_as_json(&self.model.snapshots.load()),
+                (API_V1, "ticket")  => ticket_as_json_response(self.model.ticket.as_ref()),
+                (API_V1, "self")    => self_as_json_response(&self.model, role == Some(Role::Admin)),
+                (API_V1, func)      => {
+                    let snapshot = self.model.snapshots.load();
+                    let clusters: Vec<_> = snapshot.clusters.iter()
+                        .map(|(name, cluster)| (name.as_str(), cluster))
+                        .collect();
+                    self.cluster_api(&request, &snapshot, &clusters, &snapshot.apps, func)
+                },
+                _ => as_error(&path, StatusCode::NotFound)
             },
 
-            _ => as_not_found(&path)
+            Route::ClusterApi(API_V1, name, func) => {
+                let snapshot = self.model.snapshots.load();
+                match snapshot.clusters.get(name) {
+                    Some(cluster) =>
+                        self.cluster_api(&request, &snapshot, &[(name, cluster)], &cluster.apps, func),
+                    None => as_error(&path, StatusCode::NotFound)
+                }
+            },
+
+            _ => as_error(&path, StatusCode::NotFound)
         };
 
         Box::new(response)
     }
 }
+
+impl Service for WebApi {
+    type Response = Response;
+    type Request = Request;
+    type Error = Error;
+    type Future = BoxedResponseFuture;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let in_flight = InFlight::new(&self.in_flight);
+
+        let started = Instant::now();
+        let peer = request.remote_addr().map_or("-".to_string(), |addr| addr.to_string());
+        let (method, path) = (request.method().clone(), request.path().to_string());
+        let origin = request.headers().get_raw("Origin")
+            .and_then(|raw| raw.one())
+            .and_then(|origin| str::from_utf8(origin).ok())
+            .map(String::from);
+        let cors = self.cors.clone();
+
+        let response = self.route(request).then(move |result| {
+            drop(in_flight);
+
+            let elapsed = started.elapsed();
+            let elapsed_ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+
+            // Access log: peer, method, path, status, duration.
+            match result {
+                Ok(mut response) => {
+                    if let (Some(cors), Some(origin)) = (cors, origin) {
+                        set_cors_headers(&mut response, &cors, &origin, method == Method::Options);
+                    }
+                    if method == Method::Head {
+                        response.set_body(Body::empty());
+                    }
+
+                    println!("{} {} {} {} {}ms", peer, method, path, response.status().as_u16(), elapsed_ms);
+                    Ok(response)
+                },
+                Err(e) => {
+                    println!("{} {} {} failed: {} {}ms", peer, method, path, e, elapsed_ms);
+                    Err(e)
+                }
+            }
+        });
+
+        Box::new(response)
+    }
+}